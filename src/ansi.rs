@@ -0,0 +1,54 @@
+/// Whether `NO_COLOR` asks for output without colours, which it does when set to anything
+/// but an empty string, see https://no-color.org.
+pub fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// Lines of the output as they would be shown in a terminal, without escape codes and with
+/// lines overwritten by a carriage return, e.g. progress bars, reduced to their last state.
+pub fn plain_lines(output: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(output);
+    let text = strip_escapes(&text);
+    text.split('\n')
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            line.rsplit('\r').next().unwrap_or_default().to_string()
+        })
+        .collect()
+}
+
+/// Removes CSI (e.g. colours), OSC (e.g. titles and links) and other escape sequences.
+fn strip_escapes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Parameters until the final byte in the range `@` to `~`
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Terminated by BEL or ST (`ESC \`)
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
@@ -1,30 +1,379 @@
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    hash::{DefaultHasher, Hasher},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
-use crossbeam::channel::{bounded, Receiver};
-use notify::{Event, RecommendedWatcher, Watcher};
+use crossbeam::channel::{bounded, unbounded, Receiver, Sender};
+use notify::{
+    event::{DataChange, ModifyKind},
+    Event, EventKind, RecommendedWatcher, Watcher,
+};
+
+/// Files larger than this are not hashed and always count as changed
+const MAX_HASHED_SIZE: u64 = 16 << 20;
+/// Hashes kept at most, all are dropped once there are more
+const MAX_HASHED_FILES: usize = 10_000;
+/// Directories of build outputs and dependencies, whose files are not hashed
+const UNHASHED_DIRS: [&str; 3] = [".git", "target", "node_modules"];
 
 pub struct ModificationAwaiter {
-    _watcher: RecommendedWatcher,
+    watcher: RecommendedWatcher,
+    /// Watched recursively until `watch_files` narrows the watch down
+    root: Option<PathBuf>,
+    /// Directories of the files given to `watch_files`
+    watched_dirs: HashSet<PathBuf>,
     pub rx: Receiver<Event>,
+    tx: Sender<Event>,
+    state: Arc<Mutex<WatchState>>,
+}
+
+#[derive(Default)]
+struct WatchState {
+    paused: bool,
+    /// Changed paths received while paused or during a git operation
+    pending: Vec<PathBuf>,
+    git_operation: Option<&'static str>,
+    git_dir: Option<PathBuf>,
+    /// Content hash per file, when changes without content changes are skipped
+    hashes: Option<HashMap<PathBuf, u64>>,
+    skipped: usize,
+    /// Files that trigger a run once the watch is narrowed down to them
+    files: Option<HashSet<PathBuf>>,
+}
+
+impl WatchState {
+    /// Updates the content hashes of the paths and returns whether none of them changed.
+    /// Files seen for the first time, and paths that weren't hashed, count as changed.
+    fn is_unchanged(&mut self, paths: &[PathBuf], new_hashes: Vec<Option<u64>>) -> bool {
+        let Some(hashes) = self.hashes.as_mut() else {
+            return false;
+        };
+        let mut unchanged = true;
+        for (path, hash) in paths.iter().zip(new_hashes) {
+            match hash {
+                Some(hash) => {
+                    if hashes.insert(path.clone(), hash) != Some(hash) {
+                        unchanged = false;
+                    }
+                }
+                None => {
+                    hashes.remove(path);
+                    unchanged = false;
+                }
+            }
+        }
+        if hashes.len() > MAX_HASHED_FILES {
+            hashes.clear();
+        }
+        unchanged
+    }
+
+    fn hold(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if !self.pending.contains(&path) {
+                self.pending.push(path);
+            }
+        }
+    }
+}
+
+/// Snapshot of the watcher state for display.
+#[derive(Clone, Default)]
+pub struct WatchStatus {
+    pub paused: bool,
+    pub pending: usize,
+    /// Name of the git operation in progress that triggers are held for, e.g. "rebase"
+    pub git_operation: Option<&'static str>,
+    /// Number of changes skipped because the content was unchanged, when enabled
+    pub skipped: Option<usize>,
+    /// Number of files watched when only the files the command read are watched
+    pub files: Option<usize>,
+}
+
+/// Handle to pause and resume a `ModificationAwaiter` from other threads.
+#[derive(Clone)]
+pub struct WatchControl {
+    tx: Sender<Event>,
+    state: Arc<Mutex<WatchState>>,
 }
 
 impl ModificationAwaiter {
-    pub fn new(p: &Path) -> Self {
+    /// Watches `p` recursively. With `skip_unchanged`, a content hash is kept per changed
+    /// file and changes that leave the content as it was, e.g. `touch`, are skipped.
+    pub fn new(p: &Path, skip_unchanged: bool) -> Self {
         let (tx, rx) = bounded(0);
-        let mut watcher =
-            notify::recommended_watcher(move |res: Result<Event, notify::Error>| match res {
-                Ok(e) => {
-                    if let notify::EventKind::Modify(_) = e.kind {
-                        let _ = tx.try_send(e);
+        let git_dir = find_git_dir(p);
+        let state = Arc::new(Mutex::new(WatchState {
+            git_dir: git_dir.clone(),
+            hashes: skip_unchanged.then(HashMap::new),
+            ..Default::default()
+        }));
+        // Events are filtered on a thread of their own, as hashing reads whole files
+        let (events_tx, events_rx) = unbounded();
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _ = events_tx.send(res);
+        })
+        .unwrap();
+        {
+            let state = state.clone();
+            let tx = tx.clone();
+            let root = p.to_path_buf();
+            thread::spawn(move || {
+                for res in events_rx {
+                    match res {
+                        Ok(e) => filter_event(e, &state, &root, git_dir.as_deref(), &tx),
+                        Err(x) => panic!("watch error: {:?}", x),
                     }
                 }
-                Err(x) => panic!("watch error: {:?}", x),
-            })
-            .unwrap();
+            });
+        }
         watcher.watch(p, notify::RecursiveMode::Recursive).unwrap();
         Self {
-            _watcher: watcher,
+            watcher,
+            root: Some(p.to_path_buf()),
+            watched_dirs: HashSet::new(),
             rx,
+            tx,
+            state,
+        }
+    }
+
+    /// Watches only `files` from now on, instead of the directory or the files given before.
+    /// The directories of the files are watched, so files replaced by editors on save are seen.
+    pub fn watch_files(&mut self, files: &[PathBuf]) {
+        let dirs = files
+            .iter()
+            .filter_map(|f| f.parent())
+            .map(Path::to_path_buf)
+            .collect::<HashSet<PathBuf>>();
+        if let Some(root) = self.root.take() {
+            let _ = self.watcher.unwatch(&root);
+        }
+        for dir in self.watched_dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&self.watched_dirs) {
+            // Directories removed since the run are picked up again after the next run
+            let _ = self.watcher.watch(dir, notify::RecursiveMode::NonRecursive);
+        }
+        self.watched_dirs = dirs;
+        self.state.lock().unwrap().files = Some(files.iter().cloned().collect());
+    }
+
+    pub fn control(&self) -> WatchControl {
+        WatchControl {
+            tx: self.tx.clone(),
+            state: self.state.clone(),
         }
     }
 }
+
+impl WatchControl {
+    pub fn pause(&self) {
+        self.state.lock().unwrap().paused = true;
+    }
+
+    /// Resumes watching. Changes made while paused are coalesced into a single event,
+    /// delivered once the awaiter is received from again, or once a git operation in
+    /// progress has finished.
+    pub fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.paused = false;
+        if state.pending.is_empty() || state.git_operation.is_some() {
+            return;
+        }
+        // A git operation started while paused, keep holding the changes until it finishes
+        if let Some(git_dir) = state.git_dir.clone() {
+            if let Some(op) = git_operation(&git_dir) {
+                state.git_operation = Some(op);
+                await_git_operation(git_dir, self.state.clone(), self.tx.clone());
+                return;
+            }
+        }
+        let pending = std::mem::take(&mut state.pending);
+        drop(state);
+        let event = coalesced_event(pending);
+        let tx = self.tx.clone();
+        // The receiver may be busy with a run, so deliver without blocking the caller
+        thread::spawn(move || tx.send(event));
+    }
+
+    pub fn toggle_pause(&self) {
+        if self.status().paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    pub fn status(&self) -> WatchStatus {
+        let state = self.state.lock().unwrap();
+        WatchStatus {
+            paused: state.paused,
+            pending: state.pending.len(),
+            git_operation: state.git_operation,
+            skipped: state.hashes.as_ref().map(|_| state.skipped),
+            files: state.files.as_ref().map(HashSet::len),
+        }
+    }
+}
+
+/// Sends on modifications of the watched files, unless their content is unchanged, or holds
+/// them while paused or during a git operation.
+fn filter_event(
+    mut e: Event,
+    state: &Arc<Mutex<WatchState>>,
+    root: &Path,
+    git_dir: Option<&Path>,
+    tx: &Sender<Event>,
+) {
+    let EventKind::Modify(_) = e.kind else {
+        return;
+    };
+    let hashing = {
+        let state = state.lock().unwrap();
+        // Other files in the directories of the watched files
+        if let Some(files) = &state.files {
+            e.paths.retain(|p| files.contains(p));
+            if e.paths.is_empty() {
+                return;
+            }
+        }
+        state.hashes.is_some()
+    };
+    // Without holding the lock, so the status can be shown while large files are read
+    let hashes = hashing.then(|| {
+        e.paths
+            .iter()
+            .map(|p| content_hash(p, root, git_dir))
+            .collect()
+    });
+
+    let mut locked = state.lock().unwrap();
+    if let Some(hashes) = hashes {
+        if locked.is_unchanged(&e.paths, hashes) {
+            locked.skipped += 1;
+            return;
+        }
+    }
+    if locked.paused {
+        locked.hold(e.paths);
+        return;
+    }
+    match (git_dir.and_then(git_operation), git_dir) {
+        (Some(op), Some(git_dir)) => {
+            locked.hold(e.paths);
+            if locked.git_operation.replace(op).is_none() {
+                await_git_operation(git_dir.to_path_buf(), state.clone(), tx.clone());
+            }
+        }
+        _ => {
+            let _ = tx.try_send(e);
+        }
+    }
+}
+
+/// Hash of the content of the file, `None` when it can't be read, is too large or is in a
+/// directory of build outputs or dependencies below the watched `root`.
+fn content_hash(path: &Path, root: &Path, git_dir: Option<&Path>) -> Option<u64> {
+    // The directories the project itself is in may have any name, e.g. `~/target/app`
+    let unhashed = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .any(|c| UNHASHED_DIRS.iter().any(|dir| c.as_os_str() == *dir));
+    if unhashed || git_dir.is_some_and(|dir| path.starts_with(dir)) {
+        return None;
+    }
+    if fs::metadata(path).ok()?.len() > MAX_HASHED_SIZE {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&fs::read(path).ok()?);
+    Some(hasher.finish())
+}
+
+fn coalesced_event(paths: Vec<PathBuf>) -> Event {
+    let mut event = Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any)));
+    event.paths = paths;
+    event
+}
+
+/// Polls until the git operation has finished, then sends the changes held during it
+/// as a single event, unless watching has been paused in the meantime.
+fn await_git_operation(git_dir: PathBuf, state: Arc<Mutex<WatchState>>, tx: Sender<Event>) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(250));
+        let operation = git_operation(&git_dir);
+        let mut state = state.lock().unwrap();
+        state.git_operation = operation;
+        if operation.is_some() {
+            continue;
+        }
+        if !state.paused && !state.pending.is_empty() {
+            let pending = std::mem::take(&mut state.pending);
+            drop(state);
+            let _ = tx.send(coalesced_event(pending));
+        }
+        break;
+    });
+}
+
+/// Finds the git directory of the repository containing `path`, following the
+/// `gitdir:` file used by worktrees and submodules.
+fn find_git_dir(path: &Path) -> Option<PathBuf> {
+    path.ancestors().find_map(|dir| {
+        let git = dir.join(".git");
+        if git.is_dir() {
+            Some(git)
+        } else if git.is_file() {
+            let content = fs::read_to_string(&git).ok()?;
+            let git_dir = PathBuf::from(content.strip_prefix("gitdir:")?.trim());
+            Some(dir.join(git_dir))
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the name of the git operation in progress, which changes files in bulk.
+fn git_operation(git_dir: &Path) -> Option<&'static str> {
+    const OPERATIONS: [(&str, &str); 6] = [
+        ("rebase-merge", "rebase"),
+        ("rebase-apply", "rebase"),
+        ("MERGE_HEAD", "merge"),
+        ("CHERRY_PICK_HEAD", "cherry-pick"),
+        ("REVERT_HEAD", "revert"),
+        ("index.lock", "operation"),
+    ];
+    OPERATIONS
+        .iter()
+        .find(|(file, _)| git_dir.join(file).exists())
+        .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_files_below_directories_named_like_build_outputs() {
+        let root = std::env::temp_dir()
+            .join(format!("amdo-hash-{}", std::process::id()))
+            .join("target/app");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("node_modules/x")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("node_modules/x/index.js"), "").unwrap();
+        let source = content_hash(&root.join("src/main.rs"), &root, None);
+        let dependency = content_hash(&root.join("node_modules/x/index.js"), &root, None);
+        let _ = fs::remove_dir_all(root.parent().unwrap().parent().unwrap());
+        assert!(source.is_some());
+        assert_eq!(dependency, None);
+    }
+}
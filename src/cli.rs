@@ -0,0 +1,286 @@
+use std::path::PathBuf;
+
+use crate::read_tracing::ReadTracing;
+
+const USAGE: &str = "Usage: amdo [OPTIONS] [--] <command>...
+       amdo ctl [--socket <path>] <rerun|rerun-failed|cancel|pause|resume|status|tail [N]>
+
+Options:
+  -e, --env <KEY=VAL>     Set an environment variable for the command, can be repeated
+      --env-file <path>   Read environment variables from a file before every run
+  -C, --cwd <dir>         Run the command in the given working directory
+      --shell <path>      Run the command with the given shell instead of $SHELL
+      --no-shell          Execute the command directly without a shell
+      --interactive       Keep the input of the command open to type into it in insert mode
+      --paused            Start with watching paused, resume with `p`
+      --skip-unchanged    Don't run when a file is saved without changing its content
+      --failed-first      On changes, rerun the failing tests before the whole suite
+      --watch-reads <strace|dep-info>  Watch only the files the last run read
+      --no-tui            Stream the output to the terminal instead of showing the TUI
+      --events-json <path>  Write state changes as JSON lines to a file, or stdout with `-`
+      --log-dir <dir>     Write the output of every run to a file in the given directory
+      --log-keep <N>      Keep the logs of the last N runs
+      --log-max-size <size>  Keep at most this much of logs, e.g. 500K, 100M or 1G
+      --socket <path>     Listen for `amdo ctl` on the given socket
+      --no-socket         Don't listen for `amdo ctl`
+  -c, --config <path>     Read settings such as key bindings from the given config file
+  -h, --help              Print this help";
+
+pub struct Options {
+    pub command: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub env_file: Option<PathBuf>,
+    pub cwd: Option<PathBuf>,
+    pub shell: Option<String>,
+    pub no_shell: bool,
+    pub interactive: bool,
+    pub config: Option<PathBuf>,
+    pub paused: bool,
+    pub skip_unchanged: bool,
+    pub failed_first: bool,
+    pub watch_reads: Option<ReadTracing>,
+    pub no_tui: bool,
+    pub events_json: Option<String>,
+    pub log_dir: Option<PathBuf>,
+    pub log_keep: Option<usize>,
+    pub log_max_size: Option<u64>,
+    pub socket: Option<PathBuf>,
+    pub no_socket: bool,
+}
+
+pub enum ParseResult {
+    Run(Box<Options>),
+    /// `amdo ctl`, sending a request to a running amdo
+    Ctl {
+        socket: Option<PathBuf>,
+        request: Vec<String>,
+    },
+    Help,
+}
+
+impl Options {
+    pub fn usage() -> &'static str {
+        USAGE
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<ParseResult, String> {
+        let mut options = Options {
+            command: vec![],
+            env: vec![],
+            env_file: None,
+            cwd: None,
+            shell: None,
+            no_shell: false,
+            interactive: false,
+            config: None,
+            paused: false,
+            skip_unchanged: false,
+            failed_first: false,
+            watch_reads: None,
+            no_tui: false,
+            events_json: None,
+            log_dir: None,
+            log_keep: None,
+            log_max_size: None,
+            socket: None,
+            no_socket: false,
+        };
+
+        let mut args = args.into_iter().peekable();
+        if args.peek().map(String::as_str) == Some("ctl") {
+            args.next();
+            let mut socket = None;
+            if args.peek().map(String::as_str) == Some("--socket") {
+                args.next();
+                socket = Some(PathBuf::from(
+                    args.next().ok_or("Missing value for --socket")?,
+                ));
+            }
+            let request = args.collect::<Vec<String>>();
+            if request.is_empty() {
+                return Err("Missing request for ctl".to_string());
+            }
+            return Ok(ParseResult::Ctl { socket, request });
+        }
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                options.command.extend(args.by_ref());
+                break;
+            }
+            if !arg.starts_with('-') {
+                options.command.push(arg);
+                options.command.extend(args.by_ref());
+                break;
+            }
+
+            // Support both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for {}", flag))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(ParseResult::Help),
+                "-e" | "--env" => {
+                    let pair = value()?;
+                    options
+                        .env
+                        .push(parse_env_pair(&pair).ok_or_else(|| {
+                            format!("Expected KEY=VAL for --env, got {:?}", pair)
+                        })?);
+                }
+                "--env-file" => options.env_file = Some(PathBuf::from(value()?)),
+                "-C" | "--cwd" => options.cwd = Some(PathBuf::from(value()?)),
+                "--shell" => options.shell = Some(value()?),
+                "--no-shell" => options.no_shell = true,
+                "--interactive" => options.interactive = true,
+                "--paused" => options.paused = true,
+                "--skip-unchanged" => options.skip_unchanged = true,
+                "--failed-first" => options.failed_first = true,
+                "--watch-reads" => options.watch_reads = Some(ReadTracing::parse(&value()?)?),
+                "--no-tui" => options.no_tui = true,
+                "--events-json" => options.events_json = Some(value()?),
+                "--log-dir" => options.log_dir = Some(PathBuf::from(value()?)),
+                "--log-keep" => {
+                    let keep = value()?;
+                    options.log_keep = match keep.parse::<usize>() {
+                        Ok(n) if n > 0 => Some(n),
+                        _ => {
+                            return Err(format!(
+                                "Expected a positive count for --log-keep, got {:?}",
+                                keep
+                            ))
+                        }
+                    };
+                }
+                "--log-max-size" => {
+                    let size = value()?;
+                    options.log_max_size = Some(parse_size(&size).ok_or_else(|| {
+                        format!(
+                            "Expected a size like 500K, 100M or 1G for --log-max-size, got {:?}",
+                            size
+                        )
+                    })?);
+                }
+                "--socket" => options.socket = Some(PathBuf::from(value()?)),
+                "--no-socket" => options.no_socket = true,
+                "-c" | "--config" => options.config = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("Unknown option: {}", arg)),
+            }
+        }
+
+        if options.shell.is_some() && options.no_shell {
+            return Err("--shell and --no-shell cannot be used together".to_string());
+        }
+
+        if options.log_dir.is_none()
+            && (options.log_keep.is_some() || options.log_max_size.is_some())
+        {
+            return Err("--log-keep and --log-max-size require --log-dir".to_string());
+        }
+
+        Ok(ParseResult::Run(Box::new(options)))
+    }
+}
+
+/// Parses a `KEY=VAL` pair as used by `--env` and env files. Surrounding quotes on the
+/// value are removed.
+pub fn parse_env_pair(pair: &str) -> Option<(String, String)> {
+    let (key, value) = pair.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() {
+        return None;
+    }
+    let value = value.trim();
+    let value = match (value.chars().next(), value.chars().last()) {
+        (Some('"'), Some('"')) | (Some('\''), Some('\'')) if value.len() >= 2 => {
+            &value[1..value.len() - 1]
+        }
+        _ => value,
+    };
+    Some((key.to_string(), value.to_string()))
+}
+
+/// Parses a size in bytes with an optional `K`, `M` or `G` suffix, e.g. `100M`.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, unit) = match size.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => size.split_at(i),
+        None => (size, ""),
+    };
+    let multiplier = match unit.trim().to_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(key: &str, value: &str) -> Option<(String, String)> {
+        Some((key.to_string(), value.to_string()))
+    }
+
+    #[test]
+    fn parses_env_pairs() {
+        assert_eq!(parse_env_pair("RUST_LOG=debug"), pair("RUST_LOG", "debug"));
+        assert_eq!(parse_env_pair(" PORT = 8080 "), pair("PORT", "8080"));
+        assert_eq!(parse_env_pair("EMPTY="), pair("EMPTY", ""));
+        assert_eq!(parse_env_pair("URL=a=b&c=d"), pair("URL", "a=b&c=d"));
+    }
+
+    #[test]
+    fn strips_matching_quotes_from_values() {
+        assert_eq!(
+            parse_env_pair(r#"GREETING="hello world""#),
+            pair("GREETING", "hello world")
+        );
+        assert_eq!(parse_env_pair("NAME='it is'"), pair("NAME", "it is"));
+        assert_eq!(parse_env_pair(r#"ONE=""#), pair("ONE", "\""));
+        assert_eq!(parse_env_pair(r#"MIXED="a'"#), pair("MIXED", "\"a'"));
+    }
+
+    #[test]
+    fn rejects_pairs_without_key() {
+        assert_eq!(parse_env_pair("NO_VALUE"), None);
+        assert_eq!(parse_env_pair("=value"), None);
+        assert_eq!(parse_env_pair(" =value"), None);
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("500K"), Some(500 << 10));
+        assert_eq!(parse_size("100m"), Some(100 << 20));
+        assert_eq!(parse_size(" 1 GB "), Some(1 << 30));
+        assert_eq!(parse_size("2b"), Some(2));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for size in [
+            "",
+            "M",
+            "1.5G",
+            "-1K",
+            "10T",
+            "10KiB",
+            "18446744073709551615G",
+        ] {
+            assert_eq!(parse_size(size), None, "{:?}", size);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const LOCAL_CONFIG: &str = ".amdo.conf";
+
+/// Settings read from an ini-like config file:
+///
+/// ```ini
+/// # Comment
+/// [keys]
+/// rerun = space, r
+/// ```
+#[derive(Default)]
+pub struct Config {
+    sections: HashMap<String, Vec<(String, String)>>,
+}
+
+impl Config {
+    /// Loads the config from `path`, or from `.amdo.conf` in the working directory or
+    /// `amdo/amdo.conf` in the user config directory, when they exist.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let path = match path {
+            Some(p) => p.to_path_buf(),
+            None => match default_paths().into_iter().find(|p| p.is_file()) {
+                Some(p) => p,
+                None => return Ok(Config::default()),
+            },
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read config {}: {}", path.display(), e))?;
+        Config::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(content: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_string();
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => config
+                    .sections
+                    .entry(section.clone())
+                    .or_default()
+                    .push((key.trim().to_string(), value.trim().to_string())),
+                None => return Err(format!("Line {}: expected `key = value`", i + 1)),
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn section(&self, section: &str) -> &[(String, String)] {
+        self.sections
+            .get(section)
+            .map(|s| s.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+fn default_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(LOCAL_CONFIG)];
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")));
+    if let Some(dir) = config_dir {
+        paths.push(dir.join("amdo").join("amdo.conf"));
+    }
+    paths
+}
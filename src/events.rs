@@ -0,0 +1,177 @@
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Result, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use notify::{event::ModifyKind, EventKind};
+
+use crate::{
+    process_manager::{RunEnd, Trigger},
+    time_format::iso8601,
+};
+
+static SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+#[derive(Clone, Copy)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// State changes emitted as newline delimited JSON with `--events-json`.
+pub enum Event<'a> {
+    WatchStarted {
+        path: &'a Path,
+        command: &'a str,
+    },
+    FileChanged {
+        paths: &'a [PathBuf],
+        kind: &'a EventKind,
+    },
+    RunStarted {
+        id: u64,
+        trigger: &'a Trigger,
+        command: &'a str,
+    },
+    OutputLine {
+        id: u64,
+        stream: Stream,
+        text: &'a str,
+    },
+    RunFinished(&'a RunEnd),
+}
+
+/// Writes events to the file at `path`, or to stdout when it is `-`.
+pub fn init(path: &str) -> Result<()> {
+    let writer: Box<dyn Write + Send> = match path {
+        "-" => Box::new(stdout()),
+        path => Box::new(BufWriter::new(File::create(path)?)),
+    };
+    let _ = SINK.set(Mutex::new(writer));
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    SINK.get().is_some()
+}
+
+/// Emits the event when an event stream is configured, otherwise does nothing.
+pub fn emit(event: Event) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let line = event.to_json();
+    let mut sink = sink.lock().unwrap();
+    // A reader that went away shouldn't take amdo down with it
+    let _ = writeln!(sink, "{}", line).and_then(|_| sink.flush());
+}
+
+impl Event<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::WatchStarted { .. } => "watch_started",
+            Event::FileChanged { .. } => "file_changed",
+            Event::RunStarted { .. } => "run_started",
+            Event::OutputLine { .. } => "output_line",
+            Event::RunFinished(_) => "run_finished",
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let mut fields = vec![
+            ("event", json_string(self.name())),
+            ("time", json_string(&iso8601(SystemTime::now()))),
+        ];
+        match self {
+            Event::WatchStarted { path, command } => {
+                fields.push(("path", json_string(&path.to_string_lossy())));
+                fields.push(("command", json_string(command)));
+            }
+            Event::FileChanged { paths, kind } => {
+                fields.push(("paths", json_paths(paths)));
+                fields.push(("kind", json_string(change_kind(kind))));
+            }
+            Event::RunStarted {
+                id,
+                trigger,
+                command,
+            } => {
+                fields.push(("id", id.to_string()));
+                fields.push(("trigger", json_string(trigger.name())));
+                fields.push(("paths", json_paths(trigger.changed_files())));
+                fields.push(("command", json_string(command)));
+            }
+            Event::OutputLine { id, stream, text } => {
+                let stream = match stream {
+                    Stream::Stdout => "stdout",
+                    Stream::Stderr => "stderr",
+                };
+                fields.push(("id", id.to_string()));
+                fields.push(("stream", json_string(stream)));
+                fields.push(("text", json_string(text)));
+            }
+            Event::RunFinished(end) => {
+                fields.push(("id", end.run_id.to_string()));
+                fields.push(("exit_code", json_option(end.code())));
+                fields.push(("signal", json_option(end.signal())));
+                fields.push(("duration_ms", end.duration.as_millis().to_string()));
+                fields.push(("cancelled", end.cancelled.to_string()));
+                fields.push(("success", end.success().to_string()));
+            }
+        }
+        json_object(fields)
+    }
+}
+
+/// Formats the fields, whose values are already JSON, as a JSON object.
+pub fn json_object(fields: Vec<(&str, String)>) -> String {
+    let fields = fields
+        .into_iter()
+        .map(|(key, value)| format!("\"{}\":{}", key, value))
+        .collect::<Vec<String>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+pub fn json_option<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_string(), |v| v.to_string())
+}
+
+pub fn json_paths(paths: &[PathBuf]) -> String {
+    let paths = paths
+        .iter()
+        .map(|p| json_string(&p.to_string_lossy()))
+        .collect::<Vec<String>>();
+    format!("[{}]", paths.join(","))
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Name of a change in the events, one of `create`, `modify`, `remove`, `rename` or `other`.
+fn change_kind(kind: &EventKind) -> &'static str {
+    match kind {
+        EventKind::Create(_) => "create",
+        EventKind::Modify(ModifyKind::Name(_)) => "rename",
+        EventKind::Modify(_) => "modify",
+        EventKind::Remove(_) => "remove",
+        EventKind::Any | EventKind::Access(_) | EventKind::Other => "other",
+    }
+}
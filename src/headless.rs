@@ -0,0 +1,156 @@
+use std::{
+    io::{stderr, stdout, IsTerminal, Result, Write},
+    time::Duration,
+};
+
+use crossbeam::channel::{Receiver, RecvTimeoutError};
+use ratatui::crossterm::terminal;
+
+use crate::{
+    ansi, notifications,
+    process_manager::{ProcessExecution, RunEnd},
+    time_format::{human_duration, iso8601},
+};
+
+const CYAN: &str = "\x1b[1;36m";
+const GREEN: &str = "\x1b[1;32m";
+const RED: &str = "\x1b[1;31m";
+const GRAY: &str = "\x1b[1;90m";
+const RESET: &str = "\x1b[0m";
+
+/// Where the output is streamed to, stderr when stdout carries the event stream.
+#[derive(Clone, Copy)]
+enum Target {
+    Stdout,
+    Stderr,
+}
+
+impl Target {
+    fn writer(&self) -> Box<dyn Write> {
+        match self {
+            Target::Stdout => Box::new(stdout().lock()),
+            Target::Stderr => Box::new(stderr().lock()),
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        match self {
+            Target::Stdout => stdout().is_terminal(),
+            Target::Stderr => stderr().is_terminal(),
+        }
+    }
+}
+
+struct Streamed {
+    execution: ProcessExecution,
+    /// Number of output bytes already written
+    written: usize,
+    ended: bool,
+}
+
+/// Streams the output of every execution to stdout, or stderr with `to_stderr`, with a banner
+/// when a run starts and ends, for use without the TUI, e.g. in a tmux pane or piped to a file.
+pub fn run(rx_pm: Receiver<ProcessExecution>, to_stderr: bool) -> Result<()> {
+    let target = match to_stderr {
+        true => Target::Stderr,
+        false => Target::Stdout,
+    };
+    let color = target.is_terminal() && !ansi::no_color();
+    let mut current: Option<Streamed> = None;
+
+    loop {
+        match rx_pm.recv_timeout(Duration::from_millis(50)) {
+            Ok(execution) => {
+                if let Some(mut old) = current.take() {
+                    write_output(&mut old, target)?;
+                    if !old.ended {
+                        let _ = old.execution.kill();
+                    }
+                }
+                // Nothing can be typed into the process without the TUI
+                execution.close_input();
+                banner(
+                    &format!(
+                        "#{} {} @ {}",
+                        execution.run_id,
+                        execution.trigger,
+                        iso8601(execution.started_at)
+                    ),
+                    CYAN,
+                    color,
+                    target,
+                )?;
+                current = Some(Streamed {
+                    execution,
+                    written: 0,
+                    ended: false,
+                });
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        if let Some(ref mut streamed) = current {
+            let end = streamed.execution.end();
+            write_output(streamed, target)?;
+            if let (false, Some(end)) = (streamed.ended, end) {
+                streamed.ended = true;
+                // Keep the banner on its own line
+                if streamed.execution.output.lock().unwrap().last() != Some(&b'\n')
+                    && streamed.written > 0
+                {
+                    target.writer().write_all(b"\n")?;
+                }
+                end_banner(&end, color, target)?;
+            }
+        }
+        let notification = notifications::take_pending();
+        if !notification.is_empty() && target.is_terminal() {
+            let mut out = target.writer();
+            out.write_all(notification.as_bytes())?;
+            out.flush()?;
+        }
+    }
+}
+
+fn write_output(streamed: &mut Streamed, target: Target) -> Result<()> {
+    let output = streamed.execution.output.lock().unwrap();
+    if output.len() > streamed.written {
+        let mut out = target.writer();
+        out.write_all(&output[streamed.written..])?;
+        out.flush()?;
+        streamed.written = output.len();
+    }
+    Ok(())
+}
+
+fn end_banner(end: &RunEnd, color: bool, target: Target) -> Result<()> {
+    let style = match (end.cancelled, end.success()) {
+        (true, _) => GRAY,
+        (false, true) => GREEN,
+        (false, false) => RED,
+    };
+    banner(
+        &format!(
+            "#{} {} in {}",
+            end.run_id,
+            end.describe(),
+            human_duration(end.duration)
+        ),
+        style,
+        color,
+        target,
+    )
+}
+
+fn banner(text: &str, style: &str, color: bool, target: Target) -> Result<()> {
+    let width = terminal::size().map(|(w, _)| w as usize).unwrap_or(80);
+    let line = format!("━━━ {} ", text);
+    let fill = "━".repeat(width.saturating_sub(line.chars().count()));
+    let mut out = target.writer();
+    match color {
+        true => writeln!(out, "{}{}{}{}", style, line, fill, RESET)?,
+        false => writeln!(out, "{}{}", line, fill)?,
+    }
+    out.flush()
+}
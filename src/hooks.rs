@@ -0,0 +1,306 @@
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Config,
+    process_manager::{read_env_file, ProcessExecution, RunEnd, Trigger},
+    time_format::human_duration,
+};
+
+const NAMES: [&str; 4] = ["on_start", "on_success", "on_failure", "on_cancel"];
+/// Hooks running longer are killed, unless `timeout` in `[hooks]` says otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often a running hook is checked for having exited, timed out or been cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Outcome of a hook, shown in the stats panel.
+#[derive(Clone)]
+pub enum HookOutcome {
+    Running,
+    /// Exit code, or `None` when killed by a signal, and the last line of its output
+    Finished {
+        code: Option<i32>,
+        duration: Duration,
+        last_line: String,
+    },
+    Failed(String),
+}
+
+#[derive(Clone)]
+pub struct HookResult {
+    pub name: &'static str,
+    pub outcome: HookOutcome,
+}
+
+/// Hooks of the most recent run.
+#[derive(Clone, Default)]
+pub struct HookResults {
+    pub run_id: u64,
+    pub results: Vec<HookResult>,
+}
+
+/// Commands run around every run, configured in the `[hooks]` section of the config:
+///
+/// ```ini
+/// [hooks]
+/// on_start = rm -rf .cache/pytest
+/// on_failure = curl -s -X POST localhost:8080/status/red
+/// timeout = 10
+/// ```
+///
+/// Hooks are waited for like the command itself, so changes they make to the watched files
+/// don't trigger another run. They are killed once they run longer than the timeout in seconds,
+/// or when cancelled.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    commands: Vec<(&'static str, String)>,
+    /// Shell used to run the hooks, `/bin/sh` when the command runs without a shell.
+    pub shell: Option<String>,
+    pub cwd: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+    /// Read before every hook, like it is for the command
+    pub env_file: Option<PathBuf>,
+    /// `None` to wait for hooks however long they take
+    timeout: Option<Duration>,
+    results: Arc<Mutex<HookResults>>,
+    running: Arc<Mutex<Option<RunningHook>>>,
+}
+
+/// The hook being waited for, killed by `Hooks::cancel` from other threads.
+struct RunningHook {
+    child: Child,
+    cancelled: bool,
+}
+
+impl Hooks {
+    pub fn load(config: &Config) -> Result<Hooks, String> {
+        let mut commands = vec![];
+        let mut timeout = Some(DEFAULT_TIMEOUT);
+        for (key, command) in config.section("hooks") {
+            if key == "timeout" {
+                let seconds = command.parse::<u64>().map_err(|_| {
+                    format!(
+                        "Expected a number of seconds for timeout in [hooks], got {:?}",
+                        command
+                    )
+                })?;
+                timeout = (seconds > 0).then(|| Duration::from_secs(seconds));
+                continue;
+            }
+            let name = NAMES
+                .into_iter()
+                .find(|name| *name == key.replace('-', "_"))
+                .ok_or_else(|| format!("Unknown hook {:?} in [hooks]", key))?;
+            commands.retain(|(n, _)| *n != name);
+            if !command.is_empty() {
+                commands.push((name, command.clone()));
+            }
+        }
+        Ok(Hooks {
+            commands,
+            timeout,
+            ..Hooks::default()
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn results(&self) -> HookResults {
+        self.results.lock().unwrap().clone()
+    }
+
+    /// Runs `on_start` for a run about to start and waits for it.
+    pub fn run_start(&self, run_id: u64, trigger: &Trigger, command: &str) {
+        *self.results.lock().unwrap() = HookResults {
+            run_id,
+            results: vec![],
+        };
+        if let Some(hook) = self.command("on_start") {
+            let env = metadata_env(run_id, trigger, command);
+            self.run(run_id, "on_start", hook, env);
+        }
+    }
+
+    /// Runs `on_success`, `on_failure` or `on_cancel` for a finished run and waits for it.
+    pub fn run_end(&self, exe: &ProcessExecution, end: &RunEnd) {
+        let (name, status) = match (end.cancelled, end.success()) {
+            (true, _) => ("on_cancel", "cancelled"),
+            (false, true) => ("on_success", "success"),
+            (false, false) => ("on_failure", "failure"),
+        };
+        let Some(hook) = self.command(name) else {
+            return;
+        };
+        let mut env = metadata_env(exe.run_id, &exe.trigger, &exe.command);
+        env.push(("AMDO_STATUS".to_string(), status.to_string()));
+        env.push((
+            "AMDO_EXIT_CODE".to_string(),
+            end.code().map(|c| c.to_string()).unwrap_or_default(),
+        ));
+        env.push((
+            "AMDO_DURATION_MS".to_string(),
+            end.duration.as_millis().to_string(),
+        ));
+        if let Some(log_path) = &exe.log_path {
+            env.push((
+                "AMDO_LOG_FILE".to_string(),
+                log_path.to_string_lossy().to_string(),
+            ));
+        }
+        self.run(exe.run_id, name, hook, env);
+    }
+
+    /// Kills the hook being waited for, returning whether one was running.
+    pub fn cancel(&self) -> bool {
+        match self.running.lock().unwrap().as_mut() {
+            Some(hook) => {
+                let _ = hook.child.kill();
+                hook.cancelled = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn command(&self, name: &str) -> Option<&str> {
+        self.commands
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, command)| command.as_str())
+    }
+
+    fn run(&self, run_id: u64, name: &'static str, hook: &str, env: Vec<(String, String)>) {
+        self.set_outcome(run_id, name, HookOutcome::Running);
+
+        let started = Instant::now();
+        let mut command = Command::new(self.shell.as_deref().unwrap_or("/bin/sh"));
+        command.arg("-c").arg(hook);
+        if let Some(env_file) = &self.env_file {
+            match read_env_file(env_file) {
+                Ok(vars) => {
+                    command.envs(vars);
+                }
+                Err(e) => {
+                    self.set_outcome(run_id, name, HookOutcome::Failed(e.to_string()));
+                    return;
+                }
+            }
+        }
+        command
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .envs(env)
+            .stdin(Stdio::null());
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        let outcome = match command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => self.wait(child, started),
+            Err(e) => HookOutcome::Failed(e.to_string()),
+        };
+        self.set_outcome(run_id, name, outcome);
+    }
+
+    /// Waits for the hook to exit, to time out or to be cancelled.
+    fn wait(&self, mut child: Child, started: Instant) -> HookOutcome {
+        let readers = [read_all(child.stdout.take()), read_all(child.stderr.take())];
+        *self.running.lock().unwrap() = Some(RunningHook {
+            child,
+            cancelled: false,
+        });
+        let mut timed_out = false;
+        let result = loop {
+            let mut running = self.running.lock().unwrap();
+            let hook = running
+                .as_mut()
+                .expect("Only taken once the hook has exited");
+            match hook.child.try_wait() {
+                Ok(Some(status)) => break Ok((status, hook.cancelled)),
+                Ok(None) if !timed_out && self.timeout.is_some_and(|t| started.elapsed() > t) => {
+                    timed_out = true;
+                    let _ = hook.child.kill();
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    let _ = hook.child.kill();
+                    break Err(e);
+                }
+            }
+            drop(running);
+            thread::sleep(POLL_INTERVAL);
+        };
+        *self.running.lock().unwrap() = None;
+        // The output of killed hooks is left behind, processes they started may hold it open
+        match result {
+            Ok((_, true)) => HookOutcome::Failed("Cancelled".to_string()),
+            Ok(_) if timed_out => HookOutcome::Failed(format!(
+                "Timed out after {}",
+                human_duration(started.elapsed())
+            )),
+            Ok((status, false)) => {
+                let mut text = String::new();
+                for reader in readers {
+                    text.push_str(&String::from_utf8_lossy(&reader.join().unwrap_or_default()));
+                }
+                HookOutcome::Finished {
+                    code: status.code(),
+                    duration: started.elapsed(),
+                    last_line: text
+                        .lines()
+                        .map(str::trim)
+                        .rfind(|l| !l.is_empty())
+                        .unwrap_or_default()
+                        .to_string(),
+                }
+            }
+            Err(e) => HookOutcome::Failed(e.to_string()),
+        }
+    }
+
+    /// Records the outcome, unless a newer run has started in the meantime.
+    fn set_outcome(&self, run_id: u64, name: &'static str, outcome: HookOutcome) {
+        let mut results = self.results.lock().unwrap();
+        if results.run_id != run_id {
+            return;
+        }
+        results.results.retain(|r| r.name != name);
+        results.results.push(HookResult { name, outcome });
+    }
+}
+
+/// Reads the output stream of a hook until it is closed.
+fn read_all<R: Read + Send + 'static>(stream: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+/// Describes the run to the hooks, like the command itself gets with `AMDO_RUN_ID` etc.
+fn metadata_env(run_id: u64, trigger: &Trigger, command: &str) -> Vec<(String, String)> {
+    let changed_files = trigger
+        .changed_files()
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect::<Vec<String>>();
+    vec![
+        ("AMDO_RUN_ID".to_string(), run_id.to_string()),
+        ("AMDO_TRIGGER".to_string(), trigger.name().to_string()),
+        ("AMDO_CHANGED_FILES".to_string(), changed_files.join("\n")),
+        ("AMDO_COMMAND".to_string(), command.to_string()),
+    ]
+}
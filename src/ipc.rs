@@ -0,0 +1,255 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    thread,
+};
+
+use crossbeam::channel::{Sender, TrySendError};
+
+use crate::{
+    await_modify::WatchControl,
+    events::{json_object, json_option, json_paths, json_string},
+    hooks::Hooks,
+    process_manager::ProcessExecution,
+    time_format::iso8601,
+};
+
+const DEFAULT_TAIL: usize = 20;
+
+static BOUND_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// What the control socket needs to act on requests.
+pub struct Controller {
+    pub current: Arc<Mutex<Option<ProcessExecution>>>,
+    pub manual_trigger_tx: Sender<()>,
+    pub rerun_failed_tx: Sender<()>,
+    pub watch: WatchControl,
+    pub hooks: Hooks,
+}
+
+/// Default socket for a watched directory, so `amdo ctl` run in the same directory finds it.
+pub fn default_socket_path(watched: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    watched.hash(&mut hasher);
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    dir.join(format!("amdo-{:016x}.sock", hasher.finish()))
+}
+
+/// Binds the control socket and serves requests on a background thread. When another amdo
+/// already listens on `path`, a socket suffixed with the process id is used instead.
+pub fn serve(path: PathBuf, controller: Controller) -> Result<PathBuf> {
+    let path = match UnixStream::connect(&path) {
+        Ok(_) => path.with_extension(format!("{}.sock", std::process::id())),
+        Err(_) => path,
+    };
+    // Left behind by an amdo that didn't exit cleanly
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    let _ = BOUND_PATH.set(path.clone());
+
+    let controller = Arc::new(controller);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let controller = controller.clone();
+            thread::spawn(move || {
+                let _ = handle_connection(stream, &controller);
+            });
+        }
+    });
+    Ok(path)
+}
+
+/// Removes the socket file, to be called before exiting.
+pub fn remove_socket() {
+    if let Some(path) = BOUND_PATH.get() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Handles a single request line per connection, the connection is closed after the response.
+fn handle_connection(stream: UnixStream, controller: &Controller) -> Result<()> {
+    let mut line = String::new();
+    BufReader::new(stream.try_clone()?).read_line(&mut line)?;
+    let response = match controller.handle(line.trim()) {
+        Ok(response) => response,
+        Err(e) => format!("error: {}", e),
+    };
+    let mut writer = stream;
+    writeln!(writer, "{}", response)
+}
+
+impl Controller {
+    fn handle(&self, request: &str) -> std::result::Result<String, String> {
+        let mut parts = request.split_whitespace();
+        let command = parts.next().unwrap_or_default();
+        match command {
+            "rerun" | "rerun-failed" => {
+                if let Some(exe) = self.running() {
+                    let _ = exe.cancel();
+                }
+                let tx = match command {
+                    "rerun" => &self.manual_trigger_tx,
+                    _ => &self.rerun_failed_tx,
+                };
+                // Full when a rerun is pending already, e.g. while a hook runs
+                if let Err(TrySendError::Disconnected(_)) = tx.try_send(()) {
+                    return Err("amdo is shutting down".to_string());
+                }
+                Ok("ok".to_string())
+            }
+            "cancel" => match self.running() {
+                Some(exe) => {
+                    exe.cancel().map_err(|e| e.to_string())?;
+                    Ok("ok".to_string())
+                }
+                None if self.hooks.cancel() => Ok("ok".to_string()),
+                None => Err("nothing is running".to_string()),
+            },
+            "pause" => {
+                self.watch.pause();
+                Ok("ok".to_string())
+            }
+            "resume" => {
+                self.watch.resume();
+                Ok("ok".to_string())
+            }
+            "status" => Ok(self.status()),
+            "tail" => {
+                let count = match parts.next() {
+                    Some(n) => n
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid line count {:?}", n))?,
+                    None => DEFAULT_TAIL,
+                };
+                Ok(self.tail(count))
+            }
+            "" => Err("empty request".to_string()),
+            _ => Err(format!("unknown command {:?}", command)),
+        }
+    }
+
+    fn running(&self) -> Option<ProcessExecution> {
+        self.current
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|exe| exe.end().is_none())
+    }
+
+    fn status(&self) -> String {
+        let watch = self.watch.status();
+        let mut fields = vec![
+            ("paused", watch.paused.to_string()),
+            ("pending", watch.pending.to_string()),
+            (
+                "git_operation",
+                watch
+                    .git_operation
+                    .map_or_else(|| "null".to_string(), json_string),
+            ),
+            ("watched_files", json_option(watch.files)),
+        ];
+        if let Some(exe) = self.current.lock().unwrap().as_ref() {
+            let end = exe.end();
+            let state = match &end {
+                None => "running",
+                Some(end) if end.cancelled => "cancelled",
+                Some(end) if end.success() => "success",
+                Some(_) => "failed",
+            };
+            fields.push(("run_id", exe.run_id.to_string()));
+            fields.push(("command", json_string(&exe.command)));
+            fields.push(("trigger", json_string(exe.trigger.name())));
+            fields.push(("paths", json_paths(exe.trigger.changed_files())));
+            fields.push(("state", json_string(state)));
+            fields.push((
+                "log",
+                exe.log_path
+                    .as_ref()
+                    .map_or_else(|| "null".to_string(), |p| json_string(&p.to_string_lossy())),
+            ));
+            fields.push((
+                "exit_code",
+                json_option(end.as_ref().and_then(|e| e.code())),
+            ));
+            fields.push(("started_at", json_string(&iso8601(exe.started_at))));
+            fields.push((
+                "ended_at",
+                exe.ended_at()
+                    .map_or_else(|| "null".to_string(), |t| json_string(&iso8601(t))),
+            ));
+            fields.push(("elapsed_ms", exe.elapsed().as_millis().to_string()));
+        }
+        json_object(fields)
+    }
+
+    fn tail(&self, count: usize) -> String {
+        let current = self.current.lock().unwrap();
+        let Some(exe) = current.as_ref() else {
+            return String::new();
+        };
+        let output = exe.output.lock().unwrap();
+        let text = String::from_utf8_lossy(&output);
+        let lines = text.lines().collect::<Vec<&str>>();
+        lines[lines.len().saturating_sub(count)..].join("\n")
+    }
+}
+
+/// Sends a request to a running amdo and prints the response, used by `amdo ctl`.
+pub fn client(socket: Option<PathBuf>, request: &[String]) -> Result<bool> {
+    let path = match socket {
+        Some(path) => path,
+        None => find_socket(&std::env::current_dir()?)?,
+    };
+    let mut stream = UnixStream::connect(&path).map_err(|e| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("Could not connect to amdo at {}: {}", path.display(), e),
+        )
+    })?;
+    writeln!(stream, "{}", request.join(" "))?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    print!("{}", response);
+    Ok(!response.starts_with("error:"))
+}
+
+/// The socket of the amdo watching `watched`, also found when it listens on a socket suffixed
+/// with its process id. Fails when several amdo watch it, as it can't tell which one is meant.
+fn find_socket(watched: &Path) -> Result<PathBuf> {
+    let default = default_socket_path(watched);
+    let (Some(dir), Some(stem)) = (default.parent(), default.file_stem()) else {
+        return Ok(default);
+    };
+    let prefix = format!("{}.", stem.to_string_lossy());
+    let mut live = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with(&prefix) && name.ends_with(".sock")
+        })
+        // Sockets left behind by an amdo that didn't exit cleanly refuse connections
+        .filter(|path| UnixStream::connect(path).is_ok())
+        .collect::<Vec<PathBuf>>();
+    live.sort();
+    match live.len() {
+        0 | 1 => Ok(live.pop().unwrap_or(default)),
+        _ => Err(Error::other(format!(
+            "Several amdo watch this directory, choose one with --socket:\n{}",
+            live.iter()
+                .map(|path| format!("  {}", path.display()))
+                .collect::<Vec<String>>()
+                .join("\n")
+        ))),
+    }
+}
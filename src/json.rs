@@ -0,0 +1,278 @@
+use std::{iter::Peekable, str::Chars};
+
+/// Arrays and objects nested deeper are rejected, rather than overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON value, for reading the JSON output of tools such as cargo.
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Member of an object, `None` for missing keys and other values.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Value] {
+        match self {
+            Value::Array(values) => values,
+            _ => &[],
+        }
+    }
+}
+
+/// Parses a JSON document, `None` when it isn't valid JSON.
+pub fn parse(text: &str) -> Option<Value> {
+    let mut chars = text.chars().peekable();
+    let value = parse_value(&mut chars, 0)?;
+    skip_whitespace(&mut chars);
+    chars.peek().is_none().then_some(value)
+}
+
+/// Parses the value at `depth` arrays and objects deep.
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Value> {
+    skip_whitespace(chars);
+    if depth >= MAX_DEPTH && matches!(chars.peek()?, '{' | '[') {
+        return None;
+    }
+    match chars.peek()? {
+        '{' => {
+            chars.next();
+            let mut fields = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Value::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                if chars.next()? != '"' {
+                    return None;
+                }
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next()? != ':' {
+                    return None;
+                }
+                fields.push((key, parse_value(chars, depth + 1)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => return Some(Value::Object(fields)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut values = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Value::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars, depth + 1)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => return Some(Value::Array(values)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => {
+            chars.next();
+            parse_string(chars).map(Value::String)
+        }
+        't' => parse_literal(chars, "true", Value::Bool(true)),
+        'f' => parse_literal(chars, "false", Value::Bool(false)),
+        'n' => parse_literal(chars, "null", Value::Null),
+        _ => {
+            let mut number = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+                number.push(c);
+            }
+            number.parse().ok().map(Value::Number)
+        }
+    }
+}
+
+/// Parses the rest of a string after its opening quote.
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                'n' => s.push('\n'),
+                't' => s.push('\t'),
+                'r' => s.push('\r'),
+                'b' => s.push('\u{8}'),
+                'f' => s.push('\u{c}'),
+                'u' => {
+                    let high = parse_hex(chars)?;
+                    // Characters outside the BMP are escaped as a surrogate pair
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = parse_hex(chars)?;
+                        0x10000 + ((high - 0xD800) << 10) + (low.checked_sub(0xDC00)?)
+                    } else {
+                        high
+                    };
+                    s.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                c => s.push(c),
+            },
+            c => s.push(c),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let hex = chars.take(4).collect::<String>();
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+fn parse_literal(chars: &mut Peekable<Chars>, literal: &str, value: Value) -> Option<Value> {
+    for expected in literal.chars() {
+        if chars.next()? != expected {
+            return None;
+        }
+    }
+    Some(value)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cargo_message() {
+        let line = r#"{"reason":"compiler-message","package_id":"path+file:///tmp/w5#0.1.0","message":{"rendered":"error[E0308]: mismatched types\n --> src/main.rs:2:18\n","level":"error","spans":[{"file_name":"src/main.rs","line_start":2,"column_start":18,"is_primary":true}],"code":{"code":"E0308","explanation":null}}}"#;
+        let value = parse(line).unwrap();
+        assert_eq!(
+            value.get("reason").and_then(Value::as_str),
+            Some("compiler-message")
+        );
+        let message = value.get("message").unwrap();
+        assert_eq!(
+            message.get("rendered").and_then(Value::as_str),
+            Some("error[E0308]: mismatched types\n --> src/main.rs:2:18\n")
+        );
+        let span = &message.get("spans").unwrap().as_array()[0];
+        assert_eq!(span.get("line_start").and_then(Value::as_f64), Some(2.0));
+        assert_eq!(span.get("is_primary").and_then(Value::as_bool), Some(true));
+        assert_eq!(
+            message.get("code").and_then(|c| c.get("explanation")),
+            Some(&Value::Null)
+        );
+    }
+
+    #[test]
+    fn parses_numbers_and_empty_containers() {
+        assert_eq!(parse(" -1.5e3 "), Some(Value::Number(-1500.0)));
+        assert_eq!(parse("[]"), Some(Value::Array(vec![])));
+        assert_eq!(parse("{ }"), Some(Value::Object(vec![])));
+        assert_eq!(
+            parse("[0, false, null]"),
+            Some(Value::Array(vec![
+                Value::Number(0.0),
+                Value::Bool(false),
+                Value::Null
+            ]))
+        );
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        assert_eq!(
+            parse(r#""a\"b\\c\/d\n\t\r\b\f""#),
+            Some(Value::String("a\"b\\c/d\n\t\r\u{8}\u{c}".to_string()))
+        );
+    }
+
+    #[test]
+    fn decodes_unicode() {
+        assert_eq!(
+            parse(r#""caf\u00e9 \u2714""#),
+            Some(Value::String("café ✔".to_string()))
+        );
+        // Surrogate pair of U+1F600
+        assert_eq!(
+            parse(r#""\ud83d\ude00""#),
+            Some(Value::String("😀".to_string()))
+        );
+        assert_eq!(
+            parse("\"naïve ✔ 😀\""),
+            Some(Value::String("naïve ✔ 😀".to_string()))
+        );
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_some());
+        assert_eq!(parse(&nested(MAX_DEPTH + 1)), None);
+        assert_eq!(parse(&"[{\"a\":".repeat(100_000)), None);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for text in [
+            "",
+            "{",
+            "[1, 2",
+            "[1,]",
+            r#"{"a": 1,}"#,
+            r#"{"a" 1}"#,
+            r#"{a: 1}"#,
+            r#""unterminated"#,
+            r#""\u12""#,
+            r#""\ud83d""#,
+            r#""\ud83dA""#,
+            "tru",
+            "nul",
+            "1 2",
+            "-",
+            r#"{"a": 1} trailing"#,
+        ] {
+            assert_eq!(parse(text), None, "{:?}", text);
+        }
+    }
+}
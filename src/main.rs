@@ -1,105 +1,345 @@
-use std::{process::exit, thread};
+use std::{
+    process::exit,
+    sync::{Arc, Mutex},
+    thread,
+};
+mod ansi;
 mod await_modify;
+mod cli;
+mod config;
+mod events;
+mod headless;
+mod hooks;
+mod ipc;
+mod json;
+mod notifications;
 mod process_manager;
+mod read_tracing;
+mod run_log;
+mod test_results;
+mod time_format;
 mod ui;
 
 use await_modify::ModificationAwaiter;
+use cli::{Options, ParseResult};
+use config::Config;
 use crossbeam::{
-    channel::{self, unbounded, Receiver},
+    channel::{self, unbounded, Receiver, Sender},
     select,
 };
-use process_manager::{EndType, ProcessExecution, Trigger};
+use events::Event;
+use hooks::Hooks;
+use notifications::Notifier;
+use process_manager::{CommandSpec, ProcessExecution, RunEnd, Trigger};
+use read_tracing::ReadTracing;
+use run_log::LogSettings;
+use test_results::TestSummary;
 
 fn main() {
-    let p = std::env::current_dir().expect("Could not get current directory");
-    let awaiter = ModificationAwaiter::new(p.as_path());
-    let command = std::env::args().skip(1).collect::<Vec<String>>().join(" ");
-    let (tx, rx) = unbounded();
-    let (manual_trigger_tx, manual_trigger_rx) = channel::bounded(0);
-    let (end_tx, end_rx) = unbounded();
-    let launcher = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-    match ProcessExecution::start_new(&launcher, command.clone(), &end_tx, Trigger::Start) {
-        Ok(process_exe) => {
-            tx.send(process_exe)
-                .expect("Could not send process execution start to unbounded channel");
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(ParseResult::Run(options)) => *options,
+        Ok(ParseResult::Ctl { socket, request }) => match ipc::client(socket, &request) {
+            Ok(true) => exit(0),
+            Ok(false) => exit(1),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                exit(1);
+            }
+        },
+        Ok(ParseResult::Help) => {
+            println!("{}", Options::usage());
+            exit(0);
         }
         Err(e) => {
-            eprintln!("Was not able to start with command: {:?}", command);
-            eprintln!("{:?}", e);
+            eprintln!("Error: {}", e);
+            eprintln!("{}", Options::usage());
             exit(1);
         }
-    }
-
-    thread::spawn(|| match ui::init(rx, manual_trigger_tx) {
-        Ok(_) => exit(0),
+    };
+    let config = match Config::load(options.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    };
+    let mut notifier = match Notifier::load(&config) {
+        Ok(notifier) => notifier,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    };
+    let mut hooks = match Hooks::load(&config) {
+        Ok(hooks) => hooks,
         Err(e) => {
-            eprintln!("Exited abnormally because of error: {:?}", e);
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    };
+    if let Some(path) = &options.events_json {
+        if path == "-" && !options.no_tui {
+            eprintln!("Error: --events-json - writes to stdout, which requires --no-tui");
             exit(1);
         }
+        if let Err(e) = events::init(path) {
+            eprintln!("Error: Could not open {} for events: {}", path, e);
+            exit(1);
+        }
+    }
+    if options.watch_reads == Some(ReadTracing::Strace) && which::which("strace").is_err() {
+        eprintln!("Error: --watch-reads strace requires strace 5.16 or newer to be installed");
+        exit(1);
+    }
+    let p = std::env::current_dir().expect("Could not get current directory");
+    let mut awaiter = ModificationAwaiter::new(p.as_path(), options.skip_unchanged);
+    events::emit(Event::WatchStarted {
+        path: &p,
+        command: &options.command.join(" "),
+    });
+    if options.paused {
+        awaiter.control().pause();
+    }
+    let (tx, rx) = unbounded();
+    // Room for one pending rerun, so asking for one doesn't block while a hook runs
+    let (manual_trigger_tx, manual_trigger_rx) = channel::bounded(1);
+    let (rerun_failed_tx, rerun_failed_rx) = channel::bounded(1);
+    let (switch_command_tx, switch_command_rx) = unbounded();
+    let (end_tx, end_rx) = unbounded();
+    let (reads_tx, reads_rx) = unbounded();
+    let shell =
+        match options.no_shell {
+            true => None,
+            false => Some(options.shell.clone().unwrap_or_else(|| {
+                std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
+            })),
+        };
+    hooks.shell = shell.clone();
+    hooks.cwd = options.cwd.clone();
+    hooks.env = options.env.clone();
+    hooks.env_file = options.env_file.clone();
+    let mut spec = CommandSpec {
+        args: options.command.clone(),
+        shell,
+        cwd: options.cwd.clone(),
+        env: options.env.clone(),
+        env_file: options.env_file.clone(),
+        interactive: options.interactive,
+        log: options.log_dir.clone().map(|dir| LogSettings {
+            dir,
+            keep: options.log_keep,
+            max_size: options.log_max_size,
+        }),
+        hooks,
+        watch_reads: options.watch_reads,
+    };
+    // Relative paths the command opens are resolved against the directory it runs in
+    let command_dir = p.join(options.cwd.clone().unwrap_or_default());
+    let current = Arc::new(Mutex::new(None));
+    start_run(&spec, &end_tx, &tx, &current, Trigger::Start);
+
+    let socket = match options.no_socket {
+        true => None,
+        false => {
+            let controller = ipc::Controller {
+                current: current.clone(),
+                manual_trigger_tx: manual_trigger_tx.clone(),
+                rerun_failed_tx: rerun_failed_tx.clone(),
+                watch: awaiter.control(),
+                hooks: spec.hooks.clone(),
+            };
+            let path = options
+                .socket
+                .clone()
+                .unwrap_or_else(|| ipc::default_socket_path(&p));
+            match ipc::serve(path, controller) {
+                Ok(path) => {
+                    eprintln!("Listening for amdo ctl on {}", path.display());
+                    Some(path)
+                }
+                Err(e) => {
+                    eprintln!("Error: Could not listen for amdo ctl: {}", e);
+                    exit(1);
+                }
+            }
+        }
+    };
+
+    let context = ui::Context {
+        rx_pm: rx,
+        manual_trigger_tx,
+        rerun_failed_tx,
+        switch_command_tx,
+        watch: awaiter.control(),
+        command: options.command.clone(),
+        socket,
+        hooks: spec.hooks.clone(),
+        cwd: options.cwd.clone(),
+        interactive: options.interactive,
+        shell: !options.no_shell,
+    };
+    let no_tui = options.no_tui;
+    // Keep stdout for the events when they are written there
+    let headless_to_stderr = options.events_json.as_deref() == Some("-");
+    thread::spawn(move || {
+        let result = match no_tui {
+            // The rest of the context stays alive, so the channels nothing sends on stay open
+            true => headless::run(context.rx_pm, headless_to_stderr),
+            false => ui::init(context, &config),
+        };
+        ipc::remove_socket();
+        match result {
+            Ok(_) => exit(0),
+            Err(e) => {
+                eprintln!("Exited abnormally because of error: {:?}", e);
+                exit(1);
+            }
+        }
     });
 
+    // Tests of the last run that got to running them
+    let mut tests: Option<TestSummary> = None;
+    // Trigger to run the whole suite with once the failing tests pass
+    let mut full_suite_trigger = None;
+    // Run whose read files are watched, as the files of an earlier run may be found after them
+    let mut watched_run = None;
     loop {
-        await_end(&end_rx);
-        select! {
-            recv(manual_trigger_rx) -> _ => {
-                match ProcessExecution::start_new(&launcher, command.clone(), &end_tx, Trigger::Manual) {
-                    Ok(process_exe) => {
-                        tx.send(process_exe)
-                            .expect("Could not send process execution start to unbounded channel");
+        let end = await_end(&end_rx);
+        events::emit(Event::RunFinished(&end));
+        notifier.notify(&end, &spec.commandline());
+        // Cloned, so `amdo ctl` isn't blocked while the hooks run and the output is parsed
+        let exe = current.lock().unwrap().clone();
+        if let Some(exe) = exe {
+            spec.hooks.run_end(&exe, &end);
+            if !end.cancelled {
+                let lines = ansi::plain_lines(&exe.output.lock().unwrap());
+                // Keep the failures when the tests didn't get to run, e.g. on a build error
+                if let Some(summary) = test_results::parse(&lines) {
+                    tests = Some(summary);
+                }
+            }
+            if let Some(tracing) = spec.watch_reads {
+                // Off the loop, as finding the `.d` files walks the whole directory
+                let trace_file = exe.trace_file.clone();
+                let command_dir = command_dir.clone();
+                let reads_tx = reads_tx.clone();
+                let cancelled = end.cancelled;
+                let run_id = end.run_id;
+                thread::spawn(move || {
+                    let files =
+                        read_tracing::files_read(tracing, trace_file.as_deref(), &command_dir);
+                    // A cancelled run may not have read everything yet
+                    if !cancelled && !files.is_empty() {
+                        let _ = reads_tx.send((run_id, files));
                     }
-                    Err(e) => {
-                        eprintln!("Error: {:?}", e);
-                        exit(1);
+                });
+            }
+        }
+        if let Some(trigger) = full_suite_trigger.take() {
+            if end.success() {
+                start_run(&spec, &end_tx, &tx, &current, trigger);
+                continue;
+            }
+        }
+        // Waits for what starts the next run, watching the files read in the meantime
+        loop {
+            select! {
+                recv(reads_rx) -> reads => {
+                    let (run_id, files) = reads.expect("Could not receive the files read");
+                    if watched_run < Some(run_id) {
+                        watched_run = Some(run_id);
+                        awaiter.watch_files(&files);
                     }
+                    continue;
                 }
-            }
-            recv(awaiter.rx) -> event => {
-                match event {
-                    Ok(e) => match ProcessExecution::start_new(
-                        &launcher,
-                        command.clone(),
-                        &end_tx,
-                        Trigger::Modify(
-                            e.paths
-                                .first()
-                                .unwrap()
-                                .to_path_buf()
-                                .to_str()
-                                .unwrap()
-                                .to_string(),
-                        ),
-                    ) {
-                        Ok(process_exe) => {
-                            tx.send(process_exe)
-                                .expect("Could not send process execution start to unbounded channel");
+                recv(manual_trigger_rx) -> _ => {
+                    start_run(&spec, &end_tx, &tx, &current, Trigger::Manual);
+                }
+                recv(rerun_failed_rx) -> _ => {
+                    full_suite_trigger =
+                        start_failed_first(&spec, tests.as_ref(), &end_tx, &tx, &current, Trigger::Manual);
+                }
+                recv(switch_command_rx) -> command => {
+                    spec.args = command.expect("Could not receive command switch");
+                    tests = None;
+                    start_run(&spec, &end_tx, &tx, &current, Trigger::Manual);
+                }
+                recv(awaiter.rx) -> event => {
+                    match event {
+                        Ok(e) => {
+                            events::emit(Event::FileChanged {
+                                paths: &e.paths,
+                                kind: &e.kind,
+                            });
+                            let trigger = Trigger::Modify(e.paths);
+                            match options.failed_first {
+                                true => {
+                                    full_suite_trigger = start_failed_first(
+                                        &spec,
+                                        tests.as_ref(),
+                                        &end_tx,
+                                        &tx,
+                                        &current,
+                                        trigger,
+                                    );
+                                }
+                                false => start_run(&spec, &end_tx, &tx, &current, trigger),
+                            }
                         }
                         Err(e) => {
-                            eprintln!("Error: {:?}", e);
+                            eprintln!("Could not rerun because: {:?}", e);
                             exit(1);
                         }
-                    },
-                    Err(e) => {
-                        eprintln!("Could not rerun because: {:?}", e);
-                        exit(1);
                     }
                 }
             }
+            break;
         }
     }
 }
 
-fn await_end(end_rx: &Receiver<EndType>) {
-    let first_end = end_rx.recv().expect("Could not receive from channel");
-    let second_end = end_rx.recv().expect("Could not receive from channel");
-    match (first_end, second_end) {
-        (EndType::Stdout, EndType::Stderr) => {}
-        (EndType::Stderr, EndType::Stdout) => {}
-        (a, b) => {
-            eprintln!(
-                "Error: Could not receive both stdout and stderr, got {:?} and {:?}",
-                a, b
+/// Runs only the tests that failed last time, or the whole suite with `trigger` when none did.
+/// Returns the trigger to run the whole suite with once the failing tests pass.
+fn start_failed_first(
+    spec: &CommandSpec,
+    tests: Option<&TestSummary>,
+    end_tx: &Sender<RunEnd>,
+    tx: &Sender<ProcessExecution>,
+    current: &Mutex<Option<ProcessExecution>>,
+    trigger: Trigger,
+) -> Option<Trigger> {
+    match tests.and_then(|t| t.failed_filter(&spec.args)) {
+        Some(filter) => {
+            start_run(
+                &spec.with_args(&filter),
+                end_tx,
+                tx,
+                current,
+                Trigger::FailedTests,
             );
-            exit(1);
+            Some(trigger)
+        }
+        None => {
+            start_run(spec, end_tx, tx, current, trigger);
+            None
         }
     }
 }
+
+/// Starts a run and hands it over to be displayed.
+fn start_run(
+    spec: &CommandSpec,
+    end_tx: &Sender<RunEnd>,
+    tx: &Sender<ProcessExecution>,
+    current: &Mutex<Option<ProcessExecution>>,
+    trigger: Trigger,
+) {
+    let process_exe = ProcessExecution::start_new(spec, end_tx, trigger);
+    *current.lock().unwrap() = Some(process_exe.clone());
+    tx.send(process_exe)
+        .expect("Could not send process execution start to unbounded channel");
+}
+
+fn await_end(end_rx: &Receiver<RunEnd>) -> RunEnd {
+    end_rx.recv().expect("Could not receive from channel")
+}
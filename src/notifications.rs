@@ -0,0 +1,185 @@
+use std::{
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use crate::{config::Config, process_manager::RunEnd, time_format::human_duration};
+
+/// Sequences for the terminal, written by the thread drawing the TUI or streaming the output so
+/// they don't end up in the middle of what it writes.
+static PENDING: Mutex<String> = Mutex::new(String::new());
+
+/// When a notification is sent for a finished run.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum When {
+    Never,
+    Always,
+    OnFailure,
+    /// When a run succeeds after a failure or fails after a success
+    OnStatusChange,
+}
+
+impl When {
+    fn parse(value: &str) -> Option<When> {
+        match value.to_lowercase().replace([' ', '_'], "-").as_str() {
+            "never" | "off" => Some(When::Never),
+            "always" => Some(When::Always),
+            "on-failure" => Some(When::OnFailure),
+            "on-status-change" => Some(When::OnStatusChange),
+            _ => None,
+        }
+    }
+}
+
+/// Notifies about finished runs with the terminal bell, OSC 9 and 777 terminal notifications
+/// and desktop notifications, each configured in the `[notify]` section of the config:
+///
+/// ```ini
+/// [notify]
+/// bell = on-failure
+/// terminal = on-status-change
+/// desktop = never
+/// ```
+pub struct Notifier {
+    bell: When,
+    terminal: When,
+    desktop: When,
+    last_success: Option<bool>,
+}
+
+impl Notifier {
+    pub fn load(config: &Config) -> Result<Notifier, String> {
+        for (key, _) in config.section("notify") {
+            if !["bell", "terminal", "desktop"].contains(&key.as_str()) {
+                return Err(format!("Unknown notification {:?} in [notify]", key));
+            }
+        }
+        let when = |key: &str| match config.get("notify", key) {
+            Some(value) => When::parse(value).ok_or_else(|| {
+                format!(
+                    "Expected always, on-failure, on-status-change or never for {}, got {:?}",
+                    key, value
+                )
+            }),
+            None => Ok(When::Never),
+        };
+        Ok(Notifier {
+            bell: when("bell")?,
+            terminal: when("terminal")?,
+            desktop: when("desktop")?,
+            last_success: None,
+        })
+    }
+
+    /// Sends the configured notifications for the end of a run. Cancelled runs are ignored.
+    pub fn notify(&mut self, end: &RunEnd, command: &str) {
+        if end.cancelled {
+            return;
+        }
+        let success = end.success();
+        let changed = self.last_success.is_some_and(|last| last != success);
+        self.last_success = Some(success);
+        let should = |when: When| match when {
+            When::Never => false,
+            When::Always => true,
+            When::OnFailure => !success,
+            When::OnStatusChange => changed,
+        };
+
+        let title = format!("amdo: {}", end.describe());
+        let body = format!("{} ({})", command, human_duration(end.duration));
+        let mut sequence = String::new();
+        if should(self.bell) {
+            sequence.push('\x07');
+        }
+        if should(self.terminal) {
+            sequence.push_str(&terminal_notification(&title, &body));
+        }
+        PENDING.lock().unwrap().push_str(&sequence);
+        if should(self.desktop) {
+            send_desktop_notification(&title, &body, success);
+        }
+    }
+}
+
+/// Takes the sequences to write to the terminal since the last call.
+pub fn take_pending() -> String {
+    std::mem::take(&mut PENDING.lock().unwrap())
+}
+
+/// OSC 9 for iTerm2, kitty and Windows Terminal, and OSC 777 for foot, urxvt and WezTerm.
+fn terminal_notification(title: &str, body: &str) -> String {
+    // Control characters would end the sequence early and `;` separates its fields
+    let clean = |text: &str| {
+        text.chars()
+            .filter(|c| !c.is_control() && *c != ';')
+            .collect::<String>()
+    };
+    let (title, body) = (clean(title), clean(body));
+    format!(
+        "\x1b]9;{}: {}\x07\x1b]777;notify;{};{}\x07",
+        title, body, title, body
+    )
+}
+
+/// Sends a freedesktop notification over D-Bus with `notify-send`, or `gdbus` when it isn't
+/// installed. Notifications that can't be sent are dropped.
+fn send_desktop_notification(title: &str, body: &str, success: bool) {
+    let mut command = if which::which("notify-send").is_ok() {
+        let mut command = Command::new("notify-send");
+        command
+            .arg("--app-name=amdo")
+            .arg(format!(
+                "--urgency={}",
+                if success { "normal" } else { "critical" }
+            ))
+            .arg(title)
+            .arg(body);
+        command
+    } else if which::which("gdbus").is_ok() {
+        let mut command = Command::new("gdbus");
+        command.args([
+            "call",
+            "--session",
+            "--dest=org.freedesktop.Notifications",
+            "--object-path=/org/freedesktop/Notifications",
+            "--method=org.freedesktop.Notifications.Notify",
+            // Otherwise the `-1` timeout, or a body starting with `-`, is read as an option
+            "--",
+            "amdo",
+            "0",
+            "",
+            title,
+            body,
+            "[]",
+            "{}",
+            "-1",
+        ]);
+        command
+    } else {
+        return;
+    };
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    // Reap the process in the background, so it doesn't linger as a zombie
+    if let Ok(mut child) = child {
+        std::thread::spawn(move || child.wait());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_control_characters_and_separators_from_notifications() {
+        assert_eq!(
+            terminal_notification("amdo: Error: 1", "make; echo \x1b]0;pwned\x07\n (2s)"),
+            "\x1b]9;amdo: Error: 1: make echo ]0pwned (2s)\x07\
+             \x1b]777;notify;amdo: Error: 1;make echo ]0pwned (2s)\x07"
+        );
+    }
+}
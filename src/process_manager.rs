@@ -1,91 +1,743 @@
 use std::{
-    io::{BufReader, Error, ErrorKind, Read},
-    process::{Child, Command, Stdio},
-    sync::{Arc, Mutex},
+    fmt::{self, Display, Formatter},
+    fs::{self, File},
+    io::{BufReader, Error, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use crossbeam::channel::Sender;
 
+use crate::{
+    cli::parse_env_pair,
+    events,
+    hooks::Hooks,
+    read_tracing::{self, ReadTracing},
+    run_log::LogSettings,
+};
+
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone)]
 pub enum Trigger {
-    Modify(String),
+    Modify(Vec<PathBuf>),
     Manual,
     Start,
+    /// Rerunning only the tests that failed in the previous run
+    FailedTests,
+}
+
+impl Trigger {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trigger::Modify(_) => "modify",
+            Trigger::Manual => "manual",
+            Trigger::Start => "start",
+            Trigger::FailedTests => "failed-tests",
+        }
+    }
+
+    pub fn changed_files(&self) -> &[PathBuf] {
+        match self {
+            Trigger::Modify(paths) => paths,
+            _ => &[],
+        }
+    }
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Manual => write!(f, "Manual"),
+            Trigger::Start => write!(f, "Start"),
+            Trigger::FailedTests => write!(f, "Failed tests"),
+            Trigger::Modify(paths) => match paths.as_slice() {
+                [] => write!(f, "Modify"),
+                [p] => write!(f, "Modify: {}", p.to_string_lossy()),
+                [p, rest @ ..] => write!(f, "Modify: {} (+{})", p.to_string_lossy(), rest.len()),
+            },
+        }
+    }
+}
+
+/// Describes how the command is launched for every run.
+#[derive(Clone)]
+pub struct CommandSpec {
+    pub args: Vec<String>,
+    /// Shell used to run the command with `-c`, or `None` to execute `args` directly.
+    pub shell: Option<String>,
+    pub cwd: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+    /// Read before every run, so changes to the file apply to the next run.
+    pub env_file: Option<PathBuf>,
+    /// Keeps stdin of the command open for insert mode, otherwise it reads nothing.
+    pub interactive: bool,
+    /// Writes the output of every run to a file when set.
+    pub log: Option<LogSettings>,
+    pub hooks: Hooks,
+    /// Records the files every run reads when set, to watch those instead.
+    pub watch_reads: Option<ReadTracing>,
 }
 
+impl CommandSpec {
+    pub fn commandline(&self) -> String {
+        self.args.join(" ")
+    }
+
+    /// The same command with arguments appended, quoted when run by a shell.
+    pub fn with_args(&self, extra: &[String]) -> CommandSpec {
+        let mut spec = self.clone();
+        spec.args.extend(extra.iter().map(|arg| match self.shell {
+            Some(_) => shell_quote(arg),
+            None => arg.clone(),
+        }));
+        spec
+    }
+
+    /// Builds the command of a run, wrapped in `strace` writing to `trace_file` when given.
+    fn build_command(
+        &self,
+        trigger: &Trigger,
+        trace_file: Option<&Path>,
+    ) -> Result<Command, Error> {
+        let mut args = match &self.shell {
+            Some(shell) => vec![
+                shell.clone(),
+                "-c".to_string(),
+                expand_placeholders(&self.commandline(), trigger, shell_quote),
+            ],
+            None => self
+                .args
+                .iter()
+                .flat_map(|arg| {
+                    if arg == "{files}" {
+                        trigger
+                            .changed_files()
+                            .iter()
+                            .map(|p| p.to_string_lossy().to_string())
+                            .collect()
+                    } else {
+                        vec![expand_placeholders(arg, trigger, str::to_string)]
+                    }
+                })
+                .collect(),
+        };
+        if let Some(trace_file) = trace_file {
+            let mut traced = vec!["strace".to_string()];
+            traced.extend(read_tracing::strace_args(trace_file));
+            traced.append(&mut args);
+            args = traced;
+        }
+        let mut args = args.into_iter();
+        let program = args
+            .next()
+            .ok_or_else(|| Error::other("No command provided"))?;
+        let mut command = Command::new(program);
+        command.args(args);
+
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        if let Some(env_file) = &self.env_file {
+            command.envs(read_env_file(env_file)?);
+        }
+        command.envs(self.env.iter().map(|(k, v)| (k, v)));
+        Ok(command)
+    }
+}
+
+/// A run of the command. Clones share the same process, so it can be controlled from
+/// several threads.
+#[derive(Clone)]
 pub struct ProcessExecution {
     pub output: Arc<Mutex<Vec<u8>>>,
-    pub child: Child,
-    pub cancelled: bool,
+    /// `None` when the command could not be started
+    child: Option<Arc<Mutex<Child>>>,
+    stdin: Arc<Mutex<Option<ChildStdin>>>,
+    cancelled: Arc<AtomicBool>,
+    end: Arc<Mutex<Option<RunEnd>>>,
     pub trigger: Trigger,
+    pub run_id: u64,
+    pub command: String,
+    pub started_at: SystemTime,
+    /// For the elapsed time, which unlike `started_at` doesn't jump with the clock
+    started: Instant,
+    /// File the output is written to with `--log-dir`
+    pub log_path: Option<PathBuf>,
+    /// File `strace` records the opened files in with `--watch-reads strace`
+    pub trace_file: Option<PathBuf>,
+}
+
+/// How a run ended, sent once both output streams are closed and the process has exited.
+#[derive(Clone, Debug)]
+pub struct RunEnd {
+    pub run_id: u64,
+    /// `None` when the exit status could not be retrieved
+    pub status: Option<ExitStatus>,
+    pub duration: Duration,
+    pub ended_at: SystemTime,
+    pub cancelled: bool,
 }
 
-#[derive(Debug)]
-pub enum EndType {
-    Stdout,
-    Stderr,
+impl RunEnd {
+    pub fn success(&self) -> bool {
+        !self.cancelled && self.status.is_some_and(|s| s.success())
+    }
+
+    pub fn code(&self) -> Option<i32> {
+        self.status.and_then(|s| s.code())
+    }
+
+    /// The signal that terminated the process, if any.
+    pub fn signal(&self) -> Option<i32> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            self.status.and_then(|s| s.signal())
+        }
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// Short description of the outcome, e.g. `Success`, `Error: 101` or `Cancelled`.
+    pub fn describe(&self) -> String {
+        match (self.cancelled, self.code(), self.signal()) {
+            (true, _, _) => "Cancelled".to_string(),
+            (false, Some(0), _) => "Success".to_string(),
+            (false, Some(code), _) => format!("Error: {}", code),
+            (false, None, Some(signal)) => format!("Killed by signal {}", signal),
+            (false, None, None) => "Error".to_string(),
+        }
+    }
 }
 
 impl ProcessExecution {
+    /// Starts a run of the command. A command that can't be started, e.g. a missing program or
+    /// env file, makes a failed run with the error as its output, so the next save can fix it.
     pub fn start_new(
-        launcher: &str,
-        commandline: String,
-        tx_end: &Sender<EndType>,
+        spec: &CommandSpec,
+        tx_end: &Sender<RunEnd>,
         trigger: Trigger,
-    ) -> Result<ProcessExecution, Error> {
-        let command_split = commandline.split_whitespace().collect::<Vec<&str>>();
-        if command_split.is_empty() {
-            return Err(Error::new(ErrorKind::Other, "No command provided"));
+    ) -> ProcessExecution {
+        let commandline = spec.commandline();
+        let run_id = NEXT_RUN_ID.fetch_add(1, Ordering::Relaxed);
+        events::emit(events::Event::RunStarted {
+            id: run_id,
+            trigger: &trigger,
+            command: &commandline,
+        });
+        spec.hooks.run_start(run_id, &trigger, &commandline);
+        let started_at = SystemTime::now();
+        let started = Instant::now();
+        let mut execution = ProcessExecution {
+            output: Arc::new(Mutex::new(Vec::new())),
+            child: None,
+            stdin: Arc::new(Mutex::new(None)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            end: Arc::new(Mutex::new(None)),
+            trigger,
+            run_id,
+            command: commandline,
+            started_at,
+            started,
+            log_path: None,
+            trace_file: None,
+        };
+        if let Err(e) = execution.spawn(spec, tx_end) {
+            let message = format!("Could not start {:?}: {}", execution.command, e);
+            emit_line(run_id, events::Stream::Stderr, message.as_bytes());
+            let mut output = execution.output.lock().unwrap();
+            output.extend_from_slice(message.as_bytes());
+            output.push(b'\n');
+            drop(output);
+            let run_end = RunEnd {
+                run_id,
+                status: not_started_status(),
+                duration: started.elapsed(),
+                ended_at: SystemTime::now(),
+                cancelled: false,
+            };
+            *execution.end.lock().unwrap() = Some(run_end.clone());
+            tx_end.send(run_end).unwrap();
         }
+        execution
+    }
 
-        let mut child = Command::new(launcher)
-            .arg("-c")
-            .arg(commandline)
+    fn spawn(&mut self, spec: &CommandSpec, tx_end: &Sender<RunEnd>) -> Result<(), Error> {
+        if self.command.split_whitespace().next().is_none() {
+            return Err(Error::other("No command provided"));
+        }
+        let run_id = self.run_id;
+        let changed_files = self
+            .trigger
+            .changed_files()
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        let (log_path, log) = match &spec.log {
+            Some(settings) => {
+                let (path, file) = settings.create(run_id, self.started_at, &self.trigger)?;
+                (Some(path), Some(Arc::new(Mutex::new(file))))
+            }
+            None => (None, None),
+        };
+        let trace_file = (spec.watch_reads == Some(ReadTracing::Strace)).then(|| {
+            std::env::temp_dir().join(format!("amdo-{}-{}.strace", std::process::id(), run_id))
+        });
+        let mut child = spec
+            .build_command(&self.trigger, trace_file.as_deref())?
+            .env("AMDO_CHANGED_FILES", changed_files.join("\n"))
+            .env("AMDO_TRIGGER", self.trigger.name())
+            .env("AMDO_RUN_ID", run_id.to_string())
+            .stdin(match spec.interactive {
+                true => Stdio::piped(),
+                false => Stdio::null(),
+            })
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()?;
+        *self.stdin.lock().unwrap() = child.stdin.take();
 
-        let output = Arc::new(Mutex::new(Vec::new()));
-        let output_clone = output.clone();
-        let err_clone = output.clone();
+        let output = &self.output;
+        let readers = [
+            child.stdout.take().map(|s| {
+                let log = log.clone();
+                read_into(s, output.clone(), log, run_id, events::Stream::Stdout)
+            }),
+            child.stderr.take().map(|s| {
+                let log = log.clone();
+                read_into(s, output.clone(), log, run_id, events::Stream::Stderr)
+            }),
+        ];
 
-        if let Some(stdout) = child.stdout.take() {
-            let mut reader = BufReader::new(stdout);
-            let mut buffer = [0; 1024];
+        let child = Arc::new(Mutex::new(child));
+        {
+            let child = child.clone();
+            let cancelled = self.cancelled.clone();
+            let end = self.end.clone();
             let tx_end = tx_end.clone();
+            let started = self.started;
             thread::spawn(move || {
-                while let Ok(bytes_read) = reader.read(&mut buffer) {
-                    if bytes_read == 0 {
-                        break; // EOF reached
-                    }
-                    let chunk = &buffer[..bytes_read];
-                    output_clone.lock().unwrap().extend_from_slice(chunk);
+                for reader in readers.into_iter().flatten() {
+                    let _ = reader.join();
                 }
-                tx_end.send(EndType::Stdout).unwrap();
+                let status = wait_for_exit(&child);
+                let run_end = RunEnd {
+                    run_id,
+                    status,
+                    duration: started.elapsed(),
+                    ended_at: SystemTime::now(),
+                    cancelled: cancelled.load(Ordering::Relaxed),
+                };
+                *end.lock().unwrap() = Some(run_end.clone());
+                tx_end.send(run_end).unwrap();
             });
         }
+        self.child = Some(child);
+        self.log_path = log_path;
+        self.trace_file = trace_file;
+        Ok(())
+    }
 
-        if let Some(stderr) = child.stderr.take() {
-            let mut reader = BufReader::new(stderr);
-            let mut buffer = [0; 1024];
-            let tx_end = tx_end.clone();
-            thread::spawn(move || {
-                while let Ok(bytes_read) = reader.read(&mut buffer) {
-                    if bytes_read == 0 {
-                        break; // EOF reached
+    /// Time the run took, or has taken so far while it is running.
+    pub fn elapsed(&self) -> Duration {
+        self.end()
+            .map_or_else(|| self.started.elapsed(), |end| end.duration)
+    }
+
+    pub fn ended_at(&self) -> Option<SystemTime> {
+        self.end().map(|end| end.ended_at)
+    }
+
+    /// `None` when the command could not be started.
+    pub fn pid(&self) -> Option<u32> {
+        self.child.as_ref().map(|child| child.lock().unwrap().id())
+    }
+
+    pub fn kill(&self) -> Result<(), Error> {
+        match &self.child {
+            Some(child) => child.lock().unwrap().kill(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn try_wait(&self) -> Result<Option<ExitStatus>, Error> {
+        match &self.child {
+            Some(child) => child.lock().unwrap().try_wait(),
+            None => Ok(self.end().and_then(|end| end.status)),
+        }
+    }
+
+    /// Kills the process and marks the run as cancelled.
+    pub fn cancel(&self) -> Result<(), Error> {
+        if self.child.is_none() {
+            return Ok(());
+        }
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.kill()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// How the run ended, or `None` while it is still running.
+    pub fn end(&self) -> Option<RunEnd> {
+        self.end.lock().unwrap().clone()
+    }
+
+    /// Writes input to the stdin of the process and echoes it to the output, as the
+    /// process is not attached to a terminal that would echo it.
+    pub fn write_input(&self, bytes: &[u8]) -> Result<(), Error> {
+        let mut stdin = self.stdin.lock().unwrap();
+        let stdin = stdin
+            .as_mut()
+            .ok_or_else(|| Error::other("Input of the process is closed"))?;
+        stdin.write_all(bytes)?;
+        stdin.flush()?;
+        self.output.lock().unwrap().extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Closes stdin of the process, signalling end of input.
+    pub fn close_input(&self) {
+        *self.stdin.lock().unwrap() = None;
+    }
+}
+
+fn read_into<R: Read + Send + 'static>(
+    stream: R,
+    output: Arc<Mutex<Vec<u8>>>,
+    log: Option<Arc<Mutex<File>>>,
+    run_id: u64,
+    kind: events::Stream,
+) -> thread::JoinHandle<()> {
+    let mut reader = BufReader::new(stream);
+    let mut buffer = [0; 1024];
+    thread::spawn(move || {
+        // Incomplete last line, kept until its newline arrives for the event stream
+        let mut line = Vec::new();
+        while let Ok(bytes_read) = reader.read(&mut buffer) {
+            if bytes_read == 0 {
+                break; // EOF reached
+            }
+            let chunk = &buffer[..bytes_read];
+            output.lock().unwrap().extend_from_slice(chunk);
+            if let Some(log) = &log {
+                // A full disk shouldn't stop the output from being shown
+                let _ = log.lock().unwrap().write_all(chunk);
+            }
+            if events::enabled() {
+                line.extend_from_slice(chunk);
+                while let Some(end) = line.iter().position(|&b| b == b'\n') {
+                    let rest = line.split_off(end + 1);
+                    emit_line(run_id, kind, &line[..end]);
+                    line = rest;
+                }
+            }
+        }
+        if !line.is_empty() {
+            emit_line(run_id, kind, &line);
+        }
+    })
+}
+
+fn emit_line(id: u64, stream: events::Stream, line: &[u8]) {
+    let text = String::from_utf8_lossy(line);
+    events::emit(events::Event::OutputLine {
+        id,
+        stream,
+        text: text.trim_end_matches('\r'),
+    });
+}
+
+/// Polls for the exit of the child without holding the lock, so it can still be killed.
+fn wait_for_exit(child: &Mutex<Child>) -> Option<ExitStatus> {
+    loop {
+        match child.lock().unwrap().try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) => {}
+            Err(_) => return None,
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Status of a run whose command could not be started, 127 like a shell that can't find the
+/// command.
+fn not_started_status() -> Option<ExitStatus> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        Some(ExitStatus::from_raw(127 << 8))
+    }
+    #[cfg(not(unix))]
+    None
+}
+
+/// Substitutes `{file}`, `{files}`, `{dir}` and `{ext}` in the command line with the
+/// paths of the trigger, passing every path through `quote`. Triggers without changed
+/// files substitute empty strings.
+fn expand_placeholders(commandline: &str, trigger: &Trigger, quote: fn(&str) -> String) -> String {
+    let files = trigger.changed_files();
+    let first = files.first();
+    let file = first
+        .map(|p| quote(&p.to_string_lossy()))
+        .unwrap_or_default();
+    let all_files = files
+        .iter()
+        .map(|p| quote(&p.to_string_lossy()))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let dir = first
+        .and_then(|p| p.parent())
+        .map(|p| quote(&p.to_string_lossy()))
+        .unwrap_or_default();
+    let ext = first
+        .and_then(|p| p.extension())
+        .map(|e| quote(&e.to_string_lossy()))
+        .unwrap_or_default();
+
+    commandline
+        .replace("{files}", &all_files)
+        .replace("{file}", &file)
+        .replace("{dir}", &dir)
+        .replace("{ext}", &ext)
+}
+
+pub fn shell_quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-+,:@%".contains(c))
+    {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// Splits a command line typed in by the user into the arguments of the command to switch to.
+/// Run by a shell, the words keep their quotes so the shell gets the command line as typed.
+/// Run directly, the quotes are removed and the program has to exist.
+pub fn parse_command(line: &str, shell: bool, cwd: Option<&Path>) -> Result<Vec<String>, String> {
+    let words = split_words(line, shell)?;
+    let program = words
+        .first()
+        .ok_or_else(|| "No command provided".to_string())?;
+    if !shell {
+        let dir = std::env::current_dir()
+            .map_err(|e| e.to_string())?
+            .join(cwd.unwrap_or(Path::new("")));
+        which::which_in(program, std::env::var_os("PATH"), dir)
+            .map_err(|_| format!("Could not find the program {:?}", program))?;
+    }
+    Ok(words)
+}
+
+/// Splits `line` into words at whitespace outside of quotes, like a shell does, keeping the
+/// quotes and backslashes in the words with `raw`.
+fn split_words(line: &str, raw: bool) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                if raw {
+                    word.push(c);
+                }
+                word.extend(chars.next());
+            }
+            '\'' | '"' => {
+                let word = word.get_or_insert_with(String::new);
+                if raw {
+                    word.push(c);
+                }
+                loop {
+                    match chars.next() {
+                        None => return Err(format!("Missing closing {} in {:?}", c, line)),
+                        Some(q) if q == c => break,
+                        // Within double quotes, a backslash only escapes characters special there
+                        Some('\\') if c == '"' => match chars.next() {
+                            Some(e) if raw || !"$`\"\\\n".contains(e) => {
+                                word.push('\\');
+                                word.push(e);
+                            }
+                            Some(e) => word.push(e),
+                            None => return Err(format!("Missing closing {} in {:?}", c, line)),
+                        },
+                        Some(other) => word.push(other),
                     }
-                    let chunk = &buffer[..bytes_read];
-                    err_clone.lock().unwrap().extend_from_slice(chunk);
                 }
-                tx_end.send(EndType::Stderr).unwrap();
-            });
+                if raw {
+                    word.push(c);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
         }
+    }
+    words.extend(word);
+    Ok(words)
+}
 
-        Ok(ProcessExecution {
-            output,
-            child,
-            cancelled: false,
-            trigger,
-        })
+/// Reads `KEY=VAL` lines from an env file, skipping blank lines, `#` comments and
+/// leading `export` keywords.
+pub fn read_env_file(path: &PathBuf) -> Result<Vec<(String, String)>, Error> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!("Could not read env file {}: {}", path.display(), e),
+        )
+    })?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| parse_env_pair(line.strip_prefix("export ").unwrap_or(line)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modified(paths: &[&str]) -> Trigger {
+        Trigger::Modify(paths.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        let trigger = modified(&["src/main.rs", "src/my file.rs"]);
+        assert_eq!(
+            expand_placeholders("rustfmt {file} # {dir} {ext}", &trigger, shell_quote),
+            "rustfmt src/main.rs # src rs"
+        );
+        assert_eq!(
+            expand_placeholders("cat {files}", &trigger, shell_quote),
+            "cat src/main.rs 'src/my file.rs'"
+        );
+        assert_eq!(
+            expand_placeholders("{file}", &modified(&["it's.txt"]), str::to_string),
+            "it's.txt"
+        );
+    }
+
+    #[test]
+    fn expands_placeholders_to_nothing_without_changed_files() {
+        assert_eq!(
+            expand_placeholders(
+                "make {files}{file}{dir}{ext}",
+                &Trigger::Manual,
+                shell_quote
+            ),
+            "make "
+        );
+    }
+
+    #[test]
+    fn leaves_plain_words_unquoted() {
+        for word in ["src/main.rs", "-vv", "tests::a,b", "user@host:8080", "100%"] {
+            assert_eq!(shell_quote(word), word);
+        }
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_quote_round_trips_through_sh() {
+        let words = [
+            "plain",
+            "",
+            "two words",
+            "it's",
+            "''",
+            "$HOME `id` $(id)",
+            "a\\b\"c",
+            "*.rs ?",
+            "new\nline",
+            "tab\there ; & | > <",
+            "ünïcödé ✔",
+        ];
+        let script = words
+            .iter()
+            .map(|w| format!("printf '%s\\0' {}", shell_quote(w)))
+            .collect::<Vec<String>>()
+            .join("; ");
+        let output = Command::new("sh").arg("-c").arg(script).output().unwrap();
+        let printed = String::from_utf8(output.stdout).unwrap();
+        let printed = printed.split_terminator('\0').collect::<Vec<&str>>();
+        assert_eq!(printed, words);
+    }
+
+    fn words(line: &str, raw: bool) -> Vec<String> {
+        split_words(line, raw).unwrap()
+    }
+
+    #[test]
+    fn splits_words_like_a_shell() {
+        assert_eq!(
+            words("  cargo   test -- --nocapture ", false),
+            ["cargo", "test", "--", "--nocapture"]
+        );
+        assert_eq!(
+            words(r#"grep -r "fn main" 'src dir'"#, false),
+            ["grep", "-r", "fn main", "src dir"]
+        );
+        assert_eq!(
+            words(r#"echo a\ b "\"q\" \n" it\'s"#, false),
+            ["echo", "a b", r#""q" \n"#, "it's"]
+        );
+        assert_eq!(words(r#"printf '' """#, false), ["printf", "", ""]);
+        assert_eq!(words("", false), Vec::<String>::new());
+    }
+
+    #[test]
+    fn keeps_quotes_of_words_for_the_shell() {
+        let line = r#"cargo test "a  b" 'it'\''s' && echo \$HOME"#;
+        assert_eq!(
+            words(line, true),
+            [
+                "cargo",
+                "test",
+                r#""a  b""#,
+                r"'it'\''s'",
+                "&&",
+                "echo",
+                r"\$HOME"
+            ]
+        );
+        assert_eq!(words(line, true).join(" "), line);
+    }
+
+    #[test]
+    fn rejects_unclosed_quotes() {
+        assert!(split_words("echo 'open", false).is_err());
+        assert!(split_words(r#"echo "open\""#, true).is_err());
+    }
+
+    #[test]
+    fn parses_commands_to_switch_to() {
+        assert_eq!(
+            parse_command("sh -c 'exit 1'", false, None),
+            Ok(vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                "exit 1".to_string()
+            ])
+        );
+        assert!(parse_command("no-such-program-amdo --flag", false, None).is_err());
+        // The shell reports missing programs itself
+        assert!(parse_command("no-such-program-amdo --flag", true, None).is_ok());
+        assert!(parse_command("   ", true, None).is_err());
     }
 }
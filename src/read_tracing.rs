@@ -0,0 +1,304 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directories of the system, whose files are opened by every run, like shared libraries and
+/// locale data, but are not inputs of a build that change while working on it.
+const SYSTEM_ROOTS: [&str; 17] = [
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/nix/store",
+    "/opt",
+    "/proc",
+    "/run",
+    "/sbin",
+    "/sys",
+    "/tmp",
+    "/usr",
+    "/var",
+    "/Library",
+];
+
+/// How the files a run reads are found with `--watch-reads`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReadTracing {
+    /// Runs the command under `strace`, recording the files the process tree opens
+    Strace,
+    /// Reads the Makefile style `.d` dependency files of cargo, gcc and clang after a run
+    DepInfo,
+}
+
+impl ReadTracing {
+    pub fn parse(name: &str) -> Result<ReadTracing, String> {
+        match name {
+            "strace" => Ok(ReadTracing::Strace),
+            "dep-info" => Ok(ReadTracing::DepInfo),
+            _ => Err(format!(
+                "Expected strace or dep-info for --watch-reads, got {:?}",
+                name
+            )),
+        }
+    }
+}
+
+/// Arguments running a command under `strace`, which writes the successful opens and execs of
+/// the process tree to `trace_file`. The process tree is killed with `strace`, which is what a
+/// cancelled run kills, instead of being left running detached.
+pub fn strace_args(trace_file: &Path) -> Vec<String> {
+    [
+        "-f",
+        "-qq",
+        "--kill-on-exit",
+        "-e",
+        "trace=open,openat,openat2,execve",
+        "-e",
+        "status=successful",
+        "-o",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .chain([trace_file.to_string_lossy().to_string(), "--".to_string()])
+    .collect()
+}
+
+/// Files the run read, as canonical paths. Relative paths are resolved against `cwd`, which is
+/// wrong for processes that change their directory. Files the run also wrote are left out as they
+/// are outputs, and so are system files unless `cwd` is among them, e.g. in `/tmp`.
+pub fn files_read(tracing: ReadTracing, trace_file: Option<&Path>, cwd: &Path) -> Vec<PathBuf> {
+    let (read, written) = match (tracing, trace_file) {
+        (ReadTracing::Strace, Some(trace_file)) => {
+            let trace = fs::read_to_string(trace_file).unwrap_or_default();
+            let _ = fs::remove_file(trace_file);
+            parse_strace(&trace)
+        }
+        (ReadTracing::Strace, None) => return vec![],
+        (ReadTracing::DepInfo, _) => (dep_info_files(cwd), vec![]),
+    };
+    let written = written
+        .iter()
+        .filter_map(|p| cwd.join(p).canonicalize().ok())
+        .collect::<HashSet<PathBuf>>();
+    let mut files = vec![];
+    for path in read {
+        let Ok(path) = cwd.join(path).canonicalize() else {
+            continue;
+        };
+        if path.is_file()
+            && !written.contains(&path)
+            && !SYSTEM_ROOTS
+                .iter()
+                .any(|root| path.starts_with(root) && !cwd.starts_with(root))
+            && !files.contains(&path)
+        {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Paths opened for reading and paths opened for writing in `strace -f` output, e.g.
+/// `1234 openat(AT_FDCWD, "src/main.rs", O_RDONLY|O_CLOEXEC) = 3`.
+fn parse_strace(trace: &str) -> (Vec<String>, Vec<String>) {
+    let mut read = vec![];
+    let mut written = vec![];
+    for line in trace.lines() {
+        // Without the pid when the command didn't fork
+        let call = line
+            .split_once(' ')
+            .filter(|(pid, _)| pid.chars().all(|c| c.is_ascii_digit()))
+            .map_or(line, |(_, call)| call)
+            .trim_start();
+        let Some((syscall, args)) = call.split_once('(') else {
+            continue;
+        };
+        if !matches!(syscall, "open" | "openat" | "openat2" | "execve") {
+            continue;
+        }
+        let Some((path, flags)) = quoted(args) else {
+            continue;
+        };
+        if ["O_WRONLY", "O_RDWR", "O_CREAT"]
+            .iter()
+            .any(|flag| flags.contains(flag))
+        {
+            written.push(path);
+        } else {
+            read.push(path);
+        }
+    }
+    (read, written)
+}
+
+/// The first quoted string in `s` with the rest after it.
+fn quoted(s: &str) -> Option<(String, &str)> {
+    let start = s.find('"')? + 1;
+    let mut value = String::new();
+    let mut chars = s[start..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &s[start + i + 1..])),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Dependencies listed in the `.d` files below `dir`, e.g. in cargo's `target` directory.
+fn dep_info_files(dir: &Path) -> Vec<String> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !name.starts_with('.') && name != "node_modules" {
+                    dirs.push(path);
+                }
+            } else if file_type.is_file() && name.ends_with(".d") {
+                if let Ok(content) = fs::read_to_string(&path) {
+                    files.extend(parse_dep_info(&content));
+                }
+            }
+        }
+    }
+    files
+}
+
+/// `target: dep1 dep2 \` rules with continued lines and spaces escaped with a backslash.
+fn parse_dep_info(content: &str) -> Vec<String> {
+    let mut deps = vec![];
+    for rule in content.replace("\\\n", " ").lines() {
+        // cargo adds `# env-dep:` comments for environment variables
+        if rule.starts_with('#') {
+            continue;
+        }
+        let Some((_, rule_deps)) = rule.split_once(": ") else {
+            continue;
+        };
+        let mut dep = String::new();
+        let mut chars = rule_deps.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(' ') => dep.push(' '),
+                    Some(c) => {
+                        dep.push('\\');
+                        dep.push(c);
+                    }
+                    None => {}
+                },
+                ' ' | '\t' => {
+                    if !dep.is_empty() {
+                        deps.push(std::mem::take(&mut dep));
+                    }
+                }
+                c => dep.push(c),
+            }
+        }
+        if !dep.is_empty() {
+            deps.push(dep);
+        }
+    }
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRACE: &str = r#"4182  execve("/usr/bin/cargo", ["cargo", "build"], 0x7ffd6f1e2a48 /* 32 vars */) = 0
+4182  openat(AT_FDCWD, "/etc/ld.so.cache", O_RDONLY|O_CLOEXEC) = 3
+4182  openat(AT_FDCWD, "/lib/x86_64-linux-gnu/libc.so.6", O_RDONLY|O_CLOEXEC) = 3
+4182  openat(AT_FDCWD, "Cargo.toml", O_RDONLY|O_CLOEXEC) = 3
+4190  openat(AT_FDCWD, "src/my file.rs", O_RDONLY|O_CLOEXEC) = 5
+4190  openat(AT_FDCWD, "src/\"quoted\".rs", O_RDONLY|O_CLOEXEC) = 6
+4190  openat(AT_FDCWD, "target/debug/deps/app.d", O_WRONLY|O_CREAT|O_TRUNC|O_CLOEXEC, 0666) = 7
+4190  openat(AT_FDCWD, "target/debug/deps/app.d", O_RDONLY|O_CLOEXEC) = 7
+4190  +++ exited with 0 +++"#;
+
+    #[test]
+    fn parses_opened_files_of_strace() {
+        let (read, written) = parse_strace(STRACE);
+        assert_eq!(
+            read,
+            [
+                "/usr/bin/cargo",
+                "/etc/ld.so.cache",
+                "/lib/x86_64-linux-gnu/libc.so.6",
+                "Cargo.toml",
+                "src/my file.rs",
+                "src/\"quoted\".rs",
+                "target/debug/deps/app.d",
+            ]
+        );
+        assert_eq!(written, ["target/debug/deps/app.d"]);
+    }
+
+    #[test]
+    fn parses_strace_without_pids() {
+        let (read, _) = parse_strace(r#"openat(AT_FDCWD, "main.c", O_RDONLY) = 3"#);
+        assert_eq!(read, ["main.c"]);
+    }
+
+    #[test]
+    fn parses_dep_info() {
+        let cargo = "/home/me/app/target/debug/app: /home/me/app/src/main.rs /home/me/app/src/ui.rs
+
+/home/me/app/target/debug/app.d: /home/me/app/src/main.rs /home/me/app/src/ui.rs
+
+/home/me/app/src/main.rs:
+/home/me/app/src/ui.rs:
+
+# env-dep:CARGO_PKG_VERSION=0.1.0
+";
+        let deps = parse_dep_info(cargo);
+        assert_eq!(
+            deps,
+            [
+                "/home/me/app/src/main.rs",
+                "/home/me/app/src/ui.rs",
+                "/home/me/app/src/main.rs",
+                "/home/me/app/src/ui.rs",
+            ]
+        );
+        let gcc = "main.o: main.c /usr/include/stdio.h my\\ header.h \\\n  util.h\n";
+        assert_eq!(
+            parse_dep_info(gcc),
+            ["main.c", "/usr/include/stdio.h", "my header.h", "util.h"]
+        );
+    }
+
+    #[test]
+    fn finds_files_read_but_not_system_files() {
+        let dir = std::env::temp_dir().join(format!("amdo-read-tracing-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("build")).unwrap();
+        fs::write(dir.join("main.c"), "").unwrap();
+        fs::write(dir.join("util.h"), "").unwrap();
+        fs::write(
+            dir.join("build/main.d"),
+            "main.o: ../main.c ../util.h /etc/hosts ../missing.h\n",
+        )
+        .unwrap();
+        let files = files_read(ReadTracing::DepInfo, None, &dir.join("build"));
+        let dir = dir.canonicalize().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(files, [dir.join("main.c"), dir.join("util.h")]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    fs::{self, File},
+    io::{Error, Result},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{process_manager::Trigger, time_format::iso8601};
+
+const PREFIX: &str = "amdo-";
+const EXTENSION: &str = "log";
+
+/// Where the output of every run is written with `--log-dir`, and how many logs are kept.
+#[derive(Clone)]
+pub struct LogSettings {
+    pub dir: PathBuf,
+    /// Number of logs to keep, including the one of the current run
+    pub keep: Option<usize>,
+    /// Total size in bytes the logs of previous runs may take up
+    pub max_size: Option<u64>,
+}
+
+impl LogSettings {
+    /// Creates the log file of a run, named after its start time, id and trigger, and removes
+    /// the oldest logs beyond the retention limits.
+    pub fn create(
+        &self,
+        run_id: u64,
+        started_at: SystemTime,
+        trigger: &Trigger,
+    ) -> Result<(PathBuf, File)> {
+        fs::create_dir_all(&self.dir).map_err(|e| with_path(e, &self.dir))?;
+        // Colons are not allowed in file names everywhere
+        let name = format!(
+            "{}{}-{:05}-{}.{}",
+            PREFIX,
+            iso8601(started_at).replace(':', ""),
+            run_id,
+            trigger.name(),
+            EXTENSION
+        );
+        let path = self.dir.join(name);
+        let file = File::create(&path).map_err(|e| with_path(e, &path))?;
+        // Logs that can't be removed are retried on the next run
+        let _ = self.prune(&path);
+        Ok((path, file))
+    }
+
+    fn prune(&self, current: &Path) -> Result<()> {
+        let mut logs = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p != current && is_run_log(p))
+            .collect::<Vec<PathBuf>>();
+        // Names start with the timestamp, so the oldest come first
+        logs.sort();
+
+        let mut remove = match self.keep {
+            Some(keep) => logs.len().saturating_sub(keep.saturating_sub(1)),
+            None => 0,
+        };
+        if let Some(max_size) = self.max_size {
+            let sizes = logs
+                .iter()
+                .map(|p| fs::metadata(p).map(|m| m.len()).unwrap_or(0))
+                .collect::<Vec<u64>>();
+            let mut total = sizes.iter().sum::<u64>();
+            let mut oldest = 0;
+            while total > max_size && oldest < sizes.len() {
+                total -= sizes[oldest];
+                oldest += 1;
+            }
+            remove = remove.max(oldest);
+        }
+        for log in &logs[..remove] {
+            fs::remove_file(log)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_run_log(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    name.starts_with(PREFIX) && path.extension().is_some_and(|e| e == EXTENSION)
+}
+
+fn with_path(e: Error, path: &Path) -> Error {
+    Error::new(
+        e.kind(),
+        format!("Could not create run log {}: {}", path.display(), e),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A log directory with four logs of 10 bytes from oldest to newest, the log of the current
+    /// run and files that aren't logs of amdo.
+    fn log_dir(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("amdo-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for run in 1..=4 {
+            let name = format!("amdo-2024-10-19T1{}0000Z-0000{}-modify.log", run, run);
+            fs::write(dir.join(name), "0123456789").unwrap();
+        }
+        let current = dir.join("amdo-2024-10-19T150000Z-00005-modify.log");
+        fs::write(&current, "").unwrap();
+        for other in ["notes.txt", "build.log", "amdo-notes.txt"] {
+            fs::write(dir.join(other), "0123456789").unwrap();
+        }
+        (dir, current)
+    }
+
+    fn remaining(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn keeps_the_newest_logs() {
+        let (dir, current) = log_dir("log-keep");
+        let settings = LogSettings {
+            dir: dir.clone(),
+            keep: Some(3),
+            max_size: None,
+        };
+        settings.prune(&current).unwrap();
+        let names = remaining(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            names,
+            [
+                "amdo-2024-10-19T130000Z-00003-modify.log",
+                "amdo-2024-10-19T140000Z-00004-modify.log",
+                "amdo-2024-10-19T150000Z-00005-modify.log",
+                "amdo-notes.txt",
+                "build.log",
+                "notes.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_the_oldest_logs_beyond_the_size() {
+        let (dir, current) = log_dir("log-size");
+        let settings = LogSettings {
+            dir: dir.clone(),
+            keep: None,
+            max_size: Some(25),
+        };
+        settings.prune(&current).unwrap();
+        let names = remaining(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            names,
+            [
+                "amdo-2024-10-19T130000Z-00003-modify.log",
+                "amdo-2024-10-19T140000Z-00004-modify.log",
+                "amdo-2024-10-19T150000Z-00005-modify.log",
+                "amdo-notes.txt",
+                "build.log",
+                "notes.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_the_stricter_limit() {
+        let (dir, current) = log_dir("log-both");
+        let settings = LogSettings {
+            dir: dir.clone(),
+            keep: Some(4),
+            max_size: Some(10),
+        };
+        settings.prune(&current).unwrap();
+        let names = remaining(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            names,
+            [
+                "amdo-2024-10-19T140000Z-00004-modify.log",
+                "amdo-2024-10-19T150000Z-00005-modify.log",
+                "amdo-notes.txt",
+                "build.log",
+                "notes.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn creates_logs_named_after_the_run() {
+        let (dir, _) = log_dir("log-create");
+        let settings = LogSettings {
+            dir: dir.clone(),
+            keep: Some(2),
+            max_size: None,
+        };
+        let (path, _) = settings
+            .create(6, SystemTime::UNIX_EPOCH, &Trigger::Manual)
+            .unwrap();
+        let names = remaining(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(path, dir.join("amdo-1970-01-01T000000Z-00006-manual.log"));
+        // The epoch sorts first, yet the log of the current run is never removed
+        assert_eq!(
+            names,
+            [
+                "amdo-1970-01-01T000000Z-00006-manual.log",
+                "amdo-2024-10-19T150000Z-00005-modify.log",
+                "amdo-notes.txt",
+                "build.log",
+                "notes.txt",
+            ]
+        );
+    }
+}
@@ -0,0 +1,613 @@
+use std::path::PathBuf;
+
+/// Test runner whose output was recognised.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Runner {
+    /// The harness of `cargo test`
+    Libtest,
+    Pytest,
+    Jest,
+    GoTest,
+}
+
+/// A place in a file, e.g. where a test failed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FailedTest {
+    pub name: String,
+    pub location: Option<Location>,
+}
+
+/// Counts and failing tests of a test run.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestSummary {
+    pub runner: Runner,
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub failing: Vec<FailedTest>,
+}
+
+impl TestSummary {
+    fn new(runner: Runner) -> Self {
+        TestSummary {
+            runner,
+            passed: 0,
+            failed: 0,
+            ignored: 0,
+            failing: vec![],
+        }
+    }
+
+    fn fail(&mut self, name: &str) {
+        if !self.failing.iter().any(|t| t.name == name) {
+            self.failing.push(FailedTest {
+                name: name.to_string(),
+                location: None,
+            });
+        }
+    }
+
+    /// Sets where the test failed, unless a location is already known.
+    fn locate(&mut self, name: &str, location: Location) {
+        if let Some(test) = self.failing.iter_mut().find(|t| t.name == name) {
+            test.location.get_or_insert(location);
+        }
+    }
+
+    /// Arguments to append to the command line of `args` so the runner only runs the failing
+    /// tests, `None` when no test failed.
+    pub fn failed_filter(&self, args: &[String]) -> Option<Vec<String>> {
+        if self.failing.is_empty() {
+            return None;
+        }
+        let names = self.failing.iter().map(|t| t.name.as_str());
+        let filter = match self.runner {
+            Runner::Libtest => {
+                let mut filter = vec![];
+                // Arguments after `--` go to the test binaries
+                if !args.iter().any(|a| a == "--") {
+                    filter.push("--".to_string());
+                }
+                filter.push("--exact".to_string());
+                filter.extend(names.map(str::to_string));
+                filter
+            }
+            // pytest remembers the failures of the last run itself
+            Runner::Pytest => vec!["--lf".to_string()],
+            Runner::Jest => {
+                // Jest matches the names of the describe blocks and the test joined by spaces
+                let names = names
+                    .map(|n| regex_escape(&n.replace(" › ", " ")))
+                    .collect::<Vec<String>>();
+                vec!["-t".to_string(), format!("^({})$", names.join("|"))]
+            }
+            Runner::GoTest => {
+                // `-run` matches subtests level by level, so only the top level tests are given
+                let mut tests = vec![];
+                for name in names.map(|n| n.split('/').next().unwrap_or(n)) {
+                    if !tests.contains(&name) {
+                        tests.push(name);
+                    }
+                }
+                vec!["-run".to_string(), format!("^({})$", tests.join("|"))]
+            }
+        };
+        Some(filter)
+    }
+}
+
+/// Parses the output of libtest, pytest, jest or go test, `None` when it contains no tests.
+pub fn parse(lines: &[String]) -> Option<TestSummary> {
+    let mut summary = parse_libtest(lines)
+        .or_else(|| parse_pytest(lines))
+        .or_else(|| parse_jest(lines))
+        .or_else(|| parse_go_test(lines))?;
+    // The counts at the end are missing when the run was cancelled
+    summary.failed = summary.failed.max(summary.failing.len());
+    Some(summary)
+}
+
+/// `test tests::adds ... ok`, `... FAILED` or `... ignored`, and where tests panicked:
+/// `thread 'tests::adds' panicked at src/lib.rs:10:9:`.
+fn parse_libtest(lines: &[String]) -> Option<TestSummary> {
+    let mut summary = TestSummary::new(Runner::Libtest);
+    let mut found = false;
+    for line in lines {
+        if let Some((name, result)) = line
+            .strip_prefix("test ")
+            .and_then(|rest| rest.split_once(" ... "))
+        {
+            found = true;
+            match result {
+                "ok" => summary.passed += 1,
+                "FAILED" => {
+                    summary.failed += 1;
+                    summary.fail(name);
+                }
+                r if r.starts_with("ignored") => summary.ignored += 1,
+                _ => {}
+            }
+        } else if let Some((name, location)) = line
+            .strip_prefix("thread '")
+            .and_then(|rest| rest.split_once("' "))
+            // Newer versions print the thread id, `thread 'tests::adds' (1234) panicked at`
+            .and_then(|(name, rest)| Some((name, rest.split_once("panicked at ")?.1)))
+        {
+            let location = location.trim_end_matches(':');
+            // The location is last, also in older versions: `panicked at 'message', src/lib.rs:10:9`
+            if let Some(location) = parse_location(location.rsplit(' ').next().unwrap_or("")) {
+                summary.locate(name, location);
+            }
+        }
+    }
+    found.then_some(summary)
+}
+
+/// `tests/test_x.py::test_a PASSED [ 50%]` with `-v`, `FAILED tests/test_x.py::test_a - ...`
+/// in the short summary and `==== 1 failed, 2 passed, 1 skipped in 0.12s ====` at the end.
+fn parse_pytest(lines: &[String]) -> Option<TestSummary> {
+    let mut summary = TestSummary::new(Runner::Pytest);
+    let mut found = false;
+    for line in lines {
+        if let Some(rest) = line
+            .strip_prefix("FAILED ")
+            .or_else(|| line.strip_prefix("ERROR "))
+        {
+            let name = rest.split(" - ").next().unwrap_or(rest).trim();
+            if name.contains("::") {
+                found = true;
+                summary.fail(name);
+            }
+        } else if let Some((name, result)) = line.split_once(' ') {
+            if name.contains("::") && (result.starts_with("FAILED") || result.starts_with("ERROR"))
+            {
+                found = true;
+                summary.fail(name);
+            }
+        }
+        // Surrounded by `=` unless run with `-q`
+        let trimmed = line.trim_matches(|c: char| c == '=' || c.is_whitespace());
+        if let Some((counts, duration)) = trimmed.rsplit_once(" in ") {
+            if !duration.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+            let mut any = false;
+            for part in counts.split(", ") {
+                let Some((count, kind)) = part.split_once(' ') else {
+                    continue;
+                };
+                let Ok(count) = count.parse::<usize>() else {
+                    continue;
+                };
+                // Other counts, e.g. `2 warnings in 0.3s`, don't make it a test run
+                match kind {
+                    "passed" | "xpassed" => summary.passed += count,
+                    "failed" | "error" | "errors" => summary.failed += count,
+                    "skipped" | "xfailed" => summary.ignored += count,
+                    "deselected" => {
+                        summary.ignored += count;
+                        continue;
+                    }
+                    _ => continue,
+                }
+                any = true;
+            }
+            found |= any;
+        }
+    }
+    for test in &mut summary.failing {
+        // The node id starts with the file of the test
+        if let Some((file, _)) = test.name.split_once("::") {
+            test.location = Some(Location {
+                file: PathBuf::from(file),
+                line: 1,
+                column: 1,
+            });
+        }
+    }
+    found.then_some(summary)
+}
+
+/// `● Suite › test name` for every failure and `Tests: 1 failed, 1 skipped, 3 passed, 5 total`
+/// at the end.
+fn parse_jest(lines: &[String]) -> Option<TestSummary> {
+    let mut summary = TestSummary::new(Runner::Jest);
+    let mut found = false;
+    let mut current = None;
+    for line in lines {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix("● ") {
+            if name != "Test suite failed to run" {
+                summary.fail(name);
+                current = Some(name.to_string());
+            }
+        } else if let Some(counts) = trimmed.strip_prefix("Tests:") {
+            found = true;
+            for part in counts.split(',') {
+                let Some((count, kind)) = part.trim().split_once(' ') else {
+                    continue;
+                };
+                let count = count.parse::<usize>().unwrap_or(0);
+                match kind {
+                    "passed" => summary.passed += count,
+                    "failed" => summary.failed += count,
+                    "skipped" | "todo" => summary.ignored += count,
+                    _ => {}
+                }
+            }
+        } else if let (Some(name), Some(at)) = (&current, trimmed.strip_prefix("at ")) {
+            // `at Object.<anonymous> (src/sum.test.js:5:17)`, skipping frames in dependencies
+            let location = at
+                .rsplit_once('(')
+                .map_or(at, |(_, l)| l)
+                .trim_end_matches(')');
+            if !location.contains("node_modules") {
+                if let Some(location) = parse_location(location) {
+                    summary.locate(name, location);
+                    current = None;
+                }
+            }
+        }
+    }
+    found.then_some(summary)
+}
+
+/// `--- FAIL: TestX (0.00s)`, `--- PASS` and `--- SKIP`, with the `    x_test.go:12: message` lines
+/// of a test after its result, or with `-v` between its `=== RUN` and its result.
+fn parse_go_test(lines: &[String]) -> Option<TestSummary> {
+    let mut summary = TestSummary::new(Runner::GoTest);
+    let mut found = false;
+    // Test that failed right before, whose messages follow without `-v`
+    let mut current: Option<String> = None;
+    // Test that runs with `-v`, and the first location each test logged before its result
+    let mut running: Option<String> = None;
+    let mut logged: Vec<(String, Location)> = vec![];
+    for line in lines {
+        let trimmed = line.trim_start();
+        if let Some(name) = trimmed
+            .strip_prefix("=== RUN ")
+            .or_else(|| trimmed.strip_prefix("=== CONT "))
+        {
+            running = Some(name.trim().to_string());
+            current = None;
+        } else if let Some((result, rest)) = trimmed
+            .strip_prefix("--- ")
+            .and_then(|rest| rest.split_once(": "))
+        {
+            let name = rest.split(' ').next().unwrap_or(rest);
+            found = true;
+            current = None;
+            running = None;
+            match result {
+                "PASS" => summary.passed += 1,
+                "FAIL" => {
+                    summary.failed += 1;
+                    summary.fail(name);
+                    // Parents fail with the location their first failing subtest logged
+                    let subtest = format!("{}/", name);
+                    if let Some((_, location)) = logged
+                        .iter()
+                        .find(|(test, _)| test == name || test.starts_with(&subtest))
+                    {
+                        summary.locate(name, location.clone());
+                    }
+                    current = Some(name.to_string());
+                }
+                "SKIP" => summary.ignored += 1,
+                _ => {}
+            }
+        } else if let Some(location) = go_location(trimmed) {
+            match (&current, &running) {
+                (Some(name), _) => {
+                    // And its parents, whose results came before
+                    for (i, _) in name.match_indices('/') {
+                        summary.locate(&name[..i], location.clone());
+                    }
+                    summary.locate(name, location);
+                }
+                (None, Some(name)) => {
+                    if !logged.iter().any(|(test, _)| test == name) {
+                        logged.push((name.clone(), location));
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+    }
+    found.then_some(summary)
+}
+
+/// `x_test.go:12` of a `    x_test.go:12: message` line.
+fn go_location(line: &str) -> Option<Location> {
+    let location = line.split(": ").next().unwrap_or_default();
+    if !location.contains("_test.go:") {
+        return None;
+    }
+    let (file, line) = location.split_once(':')?;
+    Some(Location {
+        file: PathBuf::from(file),
+        line: line.parse().ok()?,
+        column: 1,
+    })
+}
+
+/// `file:line:column`
+fn parse_location(location: &str) -> Option<Location> {
+    let (rest, column) = location.rsplit_once(':')?;
+    let (file, line) = rest.rsplit_once(':')?;
+    Some(Location {
+        file: PathBuf::from(file),
+        line: line.parse().ok()?,
+        column: column.parse().ok()?,
+    })
+}
+
+fn regex_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    fn location(file: &str, line: u32, column: u32) -> Option<Location> {
+        Some(Location {
+            file: PathBuf::from(file),
+            line,
+            column,
+        })
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    const LIBTEST: &str =
+        "     Running unittests src/main.rs (target/debug/deps/x-acd64d38a2f55a35)
+
+running 3 tests
+test tests::bad ... FAILED
+test tests::good ... ok
+test tests::skip ... ignored
+
+failures:
+
+---- tests::bad stdout ----
+
+thread 'tests::bad' (11951) panicked at src/main.rs:7:16:
+assertion `left == right` failed
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+
+failures:
+    tests::bad
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+error: test failed, to rerun pass `--bin x`";
+
+    #[test]
+    fn parses_libtest_output() {
+        let summary = parse(&lines(LIBTEST)).unwrap();
+        assert_eq!(summary.runner, Runner::Libtest);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (1, 1, 1));
+        assert_eq!(
+            summary.failing,
+            [FailedTest {
+                name: "tests::bad".to_string(),
+                location: location("src/main.rs", 7, 16),
+            }]
+        );
+    }
+
+    #[test]
+    fn locates_panics_of_older_libtest_versions() {
+        let output = "test tests::bad ... FAILED
+thread 'tests::bad' panicked at 'assertion failed: false', src/lib.rs:10:9";
+        let summary = parse(&lines(output)).unwrap();
+        assert_eq!(summary.failing[0].location, location("src/lib.rs", 10, 9));
+    }
+
+    #[test]
+    fn filters_libtest_failures() {
+        let summary = parse(&lines(LIBTEST)).unwrap();
+        assert_eq!(
+            summary.failed_filter(&args(&["cargo", "test"])),
+            Some(args(&["--", "--exact", "tests::bad"]))
+        );
+        assert_eq!(
+            summary.failed_filter(&args(&["cargo", "test", "--", "--nocapture"])),
+            Some(args(&["--exact", "tests::bad"]))
+        );
+    }
+
+    const PYTEST: &str =
+        "============================= test session starts ==============================
+platform linux -- Python 3.12.3, pytest-8.3.3, pluggy-1.5.0
+rootdir: /tmp/p
+collected 4 items
+
+tests/test_math.py::test_add PASSED                                      [ 25%]
+tests/test_math.py::test_sub FAILED                                      [ 50%]
+tests/test_math.py::test_div SKIPPED (no division yet)                   [ 75%]
+tests/test_io.py::test_read ERROR                                        [100%]
+
+=========================== short test summary info ============================
+FAILED tests/test_math.py::test_sub - assert 1 == 2
+ERROR tests/test_io.py::test_read - FileNotFoundError: [Errno 2] No such file or directory
+========= 1 failed, 1 passed, 1 skipped, 1 error, 2 warnings in 0.12s ==========";
+
+    #[test]
+    fn parses_pytest_output() {
+        let summary = parse(&lines(PYTEST)).unwrap();
+        assert_eq!(summary.runner, Runner::Pytest);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (1, 2, 1));
+        assert_eq!(
+            summary.failing,
+            [
+                FailedTest {
+                    name: "tests/test_math.py::test_sub".to_string(),
+                    location: location("tests/test_math.py", 1, 1),
+                },
+                FailedTest {
+                    name: "tests/test_io.py::test_read".to_string(),
+                    location: location("tests/test_io.py", 1, 1),
+                },
+            ]
+        );
+        assert_eq!(
+            summary.failed_filter(&args(&["pytest", "-v"])),
+            Some(args(&["--lf"]))
+        );
+    }
+
+    #[test]
+    fn parses_quiet_pytest_summary() {
+        let summary = parse(&lines(
+            "....                                      [100%]\n4 passed in 0.02s",
+        ))
+        .unwrap();
+        assert_eq!((summary.passed, summary.failed), (4, 0));
+    }
+
+    #[test]
+    fn ignores_summaries_without_tests() {
+        assert_eq!(parse(&lines("2 warnings in 0.3s")), None);
+        assert_eq!(
+            parse(&lines("==== 3 deselected, 1 warning in 0.05s ====")),
+            None
+        );
+        assert_eq!(parse(&lines("Compiled 12 files in 1.5s")), None);
+    }
+
+    const JEST: &str = " FAIL  src/sum.test.js
+  math
+    ✓ adds (2 ms)
+    ✕ subtracts (3 ms)
+
+  ● math › subtracts
+
+    expect(received).toBe(expected) // Object.is equality
+
+    Expected: 1
+    Received: 2
+
+      4 | test('subtracts', () => {
+    > 5 |   expect(2 - 1 + 1).toBe(1);
+        |                     ^
+
+      at Object.toBe (node_modules/expect/build/index.js:10:3)
+      at Object.<anonymous> (src/sum.test.js:5:21)
+
+Test Suites: 1 failed, 1 total
+Tests:       1 failed, 1 skipped, 1 passed, 3 total
+Snapshots:   0 total
+Time:        0.512 s";
+
+    #[test]
+    fn parses_jest_output() {
+        let summary = parse(&lines(JEST)).unwrap();
+        assert_eq!(summary.runner, Runner::Jest);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (1, 1, 1));
+        assert_eq!(
+            summary.failing,
+            [FailedTest {
+                name: "math › subtracts".to_string(),
+                location: location("src/sum.test.js", 5, 21),
+            }]
+        );
+        assert_eq!(
+            summary.failed_filter(&args(&["npx", "jest"])),
+            Some(args(&["-t", "^(math subtracts)$"]))
+        );
+    }
+
+    const GO_TEST: &str = "=== RUN   TestAdd
+--- PASS: TestAdd (0.00s)
+=== RUN   TestSub
+=== RUN   TestSub/negative
+    math_test.go:14: got 2, want 1
+--- FAIL: TestSub (0.00s)
+    --- FAIL: TestSub/negative (0.00s)
+=== RUN   TestDiv
+    math_test.go:20: not implemented
+--- SKIP: TestDiv (0.00s)
+FAIL
+exit status 1
+FAIL	example.com/math	0.002s";
+
+    #[test]
+    fn parses_go_test_output() {
+        let summary = parse(&lines(GO_TEST)).unwrap();
+        assert_eq!(summary.runner, Runner::GoTest);
+        assert_eq!((summary.passed, summary.failed, summary.ignored), (1, 2, 1));
+        assert_eq!(
+            summary.failing,
+            [
+                FailedTest {
+                    name: "TestSub".to_string(),
+                    location: location("math_test.go", 14, 1),
+                },
+                FailedTest {
+                    name: "TestSub/negative".to_string(),
+                    location: location("math_test.go", 14, 1),
+                },
+            ]
+        );
+        assert_eq!(
+            summary.failed_filter(&args(&["go", "test", "-v", "./..."])),
+            Some(args(&["-run", "^(TestSub)$"]))
+        );
+    }
+
+    #[test]
+    fn parses_quiet_go_test_output() {
+        let output = "--- FAIL: TestSub (0.00s)
+    --- FAIL: TestSub/negative (0.00s)
+        math_test.go:14: got 2, want 1
+--- FAIL: TestMul (0.00s)
+    math_test.go:31: got 6, want 8
+FAIL
+FAIL	example.com/math	0.002s";
+        let summary = parse(&lines(output)).unwrap();
+        assert_eq!(summary.failed, 3);
+        let locations = summary
+            .failing
+            .iter()
+            .map(|t| (t.name.as_str(), t.location.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [
+                ("TestSub", location("math_test.go", 14, 1)),
+                ("TestSub/negative", location("math_test.go", 14, 1)),
+                ("TestMul", location("math_test.go", 31, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_regex_characters() {
+        assert_eq!(regex_escape("a.b (1) [x]"), r"a\.b \(1\) \[x\]");
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::json;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A compiler error or warning at a location in a file.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: u32,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Extracts diagnostics from the output of cargo and rustc, in both the human and
+/// `--message-format=json` formats, gcc, clang and tsc, while the output grows. Every line is
+/// parsed once. Notes and duplicates are left out.
+#[derive(Default)]
+pub struct DiagnosticParser {
+    /// Number of lines parsed so far
+    parsed: usize,
+    seen: HashSet<Diagnostic>,
+    /// Whether files with spaces in their path exist, as such paths may just be prose
+    exists: HashMap<PathBuf, bool>,
+}
+
+impl DiagnosticParser {
+    /// Parses the lines added since the last call and returns the diagnostics found in them.
+    /// The last line is left for later unless the output is `complete`, as rustc gives the
+    /// location on the line after the message.
+    pub fn parse(&mut self, lines: &[String], complete: bool) -> Vec<Diagnostic> {
+        let until = match complete {
+            true => lines.len(),
+            false => lines.len().saturating_sub(1),
+        };
+        let mut diagnostics = vec![];
+        for i in self.parsed..until {
+            let line = &lines[i];
+            let diagnostic = parse_cargo_json(line)
+                .or_else(|| parse_rustc(line, lines.get(i + 1)))
+                .or_else(|| parse_gcc(line))
+                .or_else(|| parse_tsc(line));
+            let Some(diagnostic) = diagnostic else {
+                continue;
+            };
+            if diagnostic.file.to_string_lossy().contains(' ') {
+                let file = diagnostic.file.clone();
+                if !*self.exists.entry(file).or_insert_with_key(|f| f.exists()) {
+                    continue;
+                }
+            }
+            if self.seen.insert(diagnostic.clone()) {
+                diagnostics.push(diagnostic);
+            }
+        }
+        self.parsed = self.parsed.max(until);
+        diagnostics
+    }
+}
+
+fn severity(level: &str) -> Option<Severity> {
+    match level {
+        "error" | "fatal error" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        _ => None,
+    }
+}
+
+/// `{"reason":"compiler-message","message":{"level":"error","spans":[...],...}}`
+fn parse_cargo_json(line: &str) -> Option<Diagnostic> {
+    if !line.starts_with("{\"reason\":\"compiler-message\"") {
+        return None;
+    }
+    let value = json::parse(line)?;
+    let message = value.get("message")?;
+    let severity = severity(message.get("level")?.as_str()?)?;
+    let span = message
+        .get("spans")?
+        .as_array()
+        .iter()
+        .find(|s| s.get("is_primary").and_then(|p| p.as_bool()) == Some(true))?;
+    Some(Diagnostic {
+        file: PathBuf::from(span.get("file_name")?.as_str()?),
+        line: span.get("line_start")?.as_f64()? as u32,
+        column: span.get("column_start")?.as_f64()? as u32,
+        severity,
+        message: message.get("message")?.as_str()?.to_string(),
+    })
+}
+
+/// `error[E0425]: cannot find value` followed by `  --> src/main.rs:2:5`
+fn parse_rustc(line: &str, next: Option<&String>) -> Option<Diagnostic> {
+    let (header, message) = line.split_once(": ")?;
+    let level = header.split_once('[').map_or(header, |(level, _)| level);
+    let severity = severity(level)?;
+    let location = next?.trim_start().strip_prefix("--> ")?;
+    let (file, line, column) = parse_location(location)?;
+    Some(Diagnostic {
+        file,
+        line,
+        column,
+        severity,
+        message: message.to_string(),
+    })
+}
+
+/// `src/main.c:3:5: error: expected ';' before '}' token`
+fn parse_gcc(line: &str) -> Option<Diagnostic> {
+    let (location, rest) = line.split_once(": ")?;
+    let (level, message) = rest.split_once(": ")?;
+    let severity = severity(level)?;
+    let (file, line, column) = parse_location(location)?;
+    Some(Diagnostic {
+        file,
+        line,
+        column,
+        severity,
+        message: message.to_string(),
+    })
+}
+
+/// `src/app.ts(3,7): error TS2322: ...` or `src/app.ts:3:7 - error TS2322: ...`
+fn parse_tsc(line: &str) -> Option<Diagnostic> {
+    let (location, rest) = match line.split_once("): ") {
+        Some((location, rest)) => {
+            let (file, position) = location.rsplit_once('(')?;
+            let (line, column) = position.split_once(',')?;
+            ((file, line, column), rest)
+        }
+        None => {
+            let (location, rest) = line.split_once(" - ")?;
+            let (rest_location, column) = location.rsplit_once(':')?;
+            let (file, line) = rest_location.rsplit_once(':')?;
+            ((file, line, column), rest)
+        }
+    };
+    let (level, rest) = rest.split_once(' ')?;
+    let (code, message) = rest.split_once(": ")?;
+    if !code.starts_with("TS") {
+        return None;
+    }
+    let (file, line, column) = location;
+    Some(Diagnostic {
+        file: PathBuf::from(file),
+        line: line.parse().ok()?,
+        column: column.parse().ok()?,
+        severity: severity(level)?,
+        message: message.to_string(),
+    })
+}
+
+/// `file:line:column`, where the file may itself contain colons.
+fn parse_location(location: &str) -> Option<(PathBuf, u32, u32)> {
+    let (rest, column) = location.rsplit_once(':')?;
+    let (file, line) = rest.rsplit_once(':')?;
+    if file.is_empty() {
+        return None;
+    }
+    Some((
+        PathBuf::from(file),
+        line.parse().ok()?,
+        column.trim().parse().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    fn parse_all(text: &str) -> Vec<Diagnostic> {
+        DiagnosticParser::default().parse(&lines(text), true)
+    }
+
+    fn diagnostic(
+        file: &str,
+        line: u32,
+        column: u32,
+        severity: Severity,
+        message: &str,
+    ) -> Diagnostic {
+        Diagnostic {
+            file: PathBuf::from(file),
+            line,
+            column,
+            severity,
+            message: message.to_string(),
+        }
+    }
+
+    const CARGO: &str = "   Compiling d1 v0.1.0 (/tmp/d1)
+warning: unused variable: `unused`
+ --> src/main.rs:2:9
+  |
+2 |     let unused = 1;
+  |         ^^^^^^ help: if this is intentional, prefix it with an underscore: `_unused`
+  |
+  = note: `#[warn(unused_variables)]` (part of `#[warn(unused)]`) on by default
+
+error[E0308]: mismatched types
+ --> src/main.rs:3:18
+  |
+3 |     let x: i32 = \"a\";
+  |            ---   ^^^ expected `i32`, found `&str`
+  |            |
+  |            expected due to this
+
+For more information about this error, try `rustc --explain E0308`.
+error: could not compile `d1` (bin \"d1\") due to 1 previous error; 1 warning emitted";
+
+    #[test]
+    fn parses_rustc_output() {
+        assert_eq!(
+            parse_all(CARGO),
+            [
+                diagnostic(
+                    "src/main.rs",
+                    2,
+                    9,
+                    Severity::Warning,
+                    "unused variable: `unused`"
+                ),
+                diagnostic("src/main.rs", 3, 18, Severity::Error, "mismatched types"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_cargo_json_output() {
+        let line = r#"{"reason":"compiler-message","package_id":"path+file:///tmp/d1#0.1.0","manifest_path":"/tmp/d1/Cargo.toml","message":{"rendered":"error[E0308]: mismatched types\n --> src/main.rs:3:18\n","$message_type":"diagnostic","children":[],"level":"error","message":"mismatched types","spans":[{"byte_end":52,"byte_start":49,"column_end":21,"column_start":18,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":"expected `i32`, found `&str`","line_end":3,"line_start":3,"suggested_replacement":null,"suggestion_applicability":null,"text":[]},{"byte_end":46,"byte_start":43,"column_end":15,"column_start":12,"expansion":null,"file_name":"src/main.rs","is_primary":false,"label":"expected due to this","line_end":3,"line_start":3,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}],"code":{"code":"E0308","explanation":null}}}
+{"reason":"build-finished","success":false}"#;
+        assert_eq!(
+            parse_all(line),
+            [diagnostic(
+                "src/main.rs",
+                3,
+                18,
+                Severity::Error,
+                "mismatched types"
+            )]
+        );
+    }
+
+    #[test]
+    fn parses_gcc_output() {
+        let output = "/tmp/d1/a.c: In function 'main':
+/tmp/d1/a.c:1:23: error: expected ',' or ';' before 'return'
+    1 | int main(){ int x = 1 return 0; }
+      |                       ^~~~~~
+/tmp/d1/a.c:1:17: warning: unused variable 'x' [-Wunused-variable]
+/tmp/d1/a.c:1:1: note: declared here";
+        assert_eq!(
+            parse_all(output),
+            [
+                diagnostic(
+                    "/tmp/d1/a.c",
+                    1,
+                    23,
+                    Severity::Error,
+                    "expected ',' or ';' before 'return'"
+                ),
+                diagnostic(
+                    "/tmp/d1/a.c",
+                    1,
+                    17,
+                    Severity::Warning,
+                    "unused variable 'x' [-Wunused-variable]"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_tsc_output() {
+        let output =
+            "src/app.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.
+src/app.ts:5:1 - error TS2304: Cannot find name 'foo'.
+
+Found 2 errors in the same file, starting at: src/app.ts:3";
+        assert_eq!(
+            parse_all(output),
+            [
+                diagnostic(
+                    "src/app.ts",
+                    3,
+                    7,
+                    Severity::Error,
+                    "Type 'string' is not assignable to type 'number'."
+                ),
+                diagnostic(
+                    "src/app.ts",
+                    5,
+                    1,
+                    Severity::Error,
+                    "Cannot find name 'foo'."
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_duplicates_and_prose() {
+        let output = "src/lib.rs:1:1: warning: twice
+src/lib.rs:1:1: warning: twice
+see the docs at some page:1:2: error: not a file";
+        assert_eq!(
+            parse_all(output),
+            [diagnostic("src/lib.rs", 1, 1, Severity::Warning, "twice")]
+        );
+    }
+
+    #[test]
+    fn parses_lines_once_as_the_output_grows() {
+        let all = lines(CARGO);
+        let mut parser = DiagnosticParser::default();
+        // The location of the warning isn't there yet
+        assert_eq!(parser.parse(&all[..2], false), []);
+        let found = parser.parse(&all[..12], false);
+        assert_eq!(found.len(), 2);
+        assert_eq!(parser.parse(&all[..12], false), []);
+        assert_eq!(parser.parse(&all, true), []);
+    }
+}
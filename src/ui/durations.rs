@@ -0,0 +1,61 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use crate::process_manager::ProcessExecution;
+
+/// Number of previous runs per command the median is taken over
+const WINDOW: usize = 10;
+
+/// Durations of the finished runs per command line, to compare a run against the median of the
+/// runs before it. Cancelled runs are left out.
+#[derive(Default)]
+pub struct RunDurations {
+    per_command: HashMap<String, VecDeque<(u64, Duration)>>,
+    last_recorded: u64,
+}
+
+impl RunDurations {
+    /// Records the duration of the run once it has finished, once per run.
+    pub fn record(&mut self, execution: &ProcessExecution) {
+        let Some(end) = execution.end() else {
+            return;
+        };
+        if execution.run_id <= self.last_recorded {
+            return;
+        }
+        self.last_recorded = execution.run_id;
+        if end.cancelled {
+            return;
+        }
+        let durations = self
+            .per_command
+            .entry(execution.command.clone())
+            .or_default();
+        durations.push_back((execution.run_id, end.duration));
+        // One more than the window, as the run itself isn't part of its median
+        if durations.len() > WINDOW + 1 {
+            durations.pop_front();
+        }
+    }
+
+    /// Median duration of the last runs of the same command before `execution`.
+    pub fn median_before(&self, execution: &ProcessExecution) -> Option<Duration> {
+        let mut durations = self
+            .per_command
+            .get(&execution.command)?
+            .iter()
+            .filter(|(run_id, _)| *run_id < execution.run_id)
+            .map(|(_, duration)| *duration)
+            .collect::<Vec<Duration>>();
+        let skip = durations.len().saturating_sub(WINDOW);
+        durations.drain(..skip);
+        durations.sort();
+        match durations.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(durations[n / 2]),
+            n => Some((durations[n / 2 - 1] + durations[n / 2]) / 2),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    io::{stdout, Error, Result},
+    path::Path,
+    process::Command,
+};
+
+use ratatui::{
+    crossterm::{
+        event::{DisableMouseCapture, EnableMouseCapture},
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+        ExecutableCommand,
+    },
+    prelude::CrosstermBackend,
+    Terminal,
+};
+
+use crate::test_results::Location;
+
+/// Opens the file at the location in `$EDITOR`, or `$VISUAL` or `vi` when it isn't set,
+/// handing the terminal over to the editor until it exits.
+pub fn open_in_editor(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    location: &Location,
+    cwd: Option<&Path>,
+) -> Result<()> {
+    let editor = std::env::var("EDITOR")
+        .or_else(|_| std::env::var("VISUAL"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut command =
+        editor_command(&editor, location).ok_or_else(|| Error::other("EDITOR is empty"))?;
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+
+    stdout().execute(DisableMouseCapture)?;
+    stdout().execute(LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    let status = command.status();
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    stdout().execute(EnableMouseCapture)?;
+    terminal.clear()?;
+
+    let status = status.map_err(|e| Error::other(format!("Could not start {}: {}", editor, e)))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(Error::other(format!("{} exited with {}", editor, status))),
+    }
+}
+
+/// Command line for the editor to open at a location, as editors disagree on how to pass it.
+fn editor_command(editor: &str, location: &Location) -> Option<Command> {
+    let Location { file, line, column } = location;
+    let mut parts = editor.split_whitespace();
+    let program = parts.next()?;
+    let mut command = Command::new(program);
+    command.args(parts);
+    let location = format!("{}:{}:{}", file.display(), line, column);
+    let name = Path::new(program)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match name.as_str() {
+        "code" | "codium" | "code-insiders" | "cursor" => command.arg("--goto").arg(location),
+        "subl" | "hx" | "helix" | "zed" => command.arg(location),
+        "emacs" | "emacsclient" => command.arg(format!("+{}:{}", line, column)).arg(file),
+        // vi, vim, nvim, nano, micro, kak and most others take `+line`
+        _ => command.arg(format!("+{}", line)).arg(file),
+    };
+    Some(command)
+}
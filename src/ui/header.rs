@@ -1,27 +1,45 @@
-use std::{path::Path, str::FromStr};
+use std::path::Path;
 
 use devicons::FileIcon;
 use ratatui::{
     layout::{Alignment, Margin, Rect},
-    style::{Color, Style, Stylize},
+    style::{Style, Stylize},
     text::{Line, Span, Text},
     widgets::{Block, Borders, Paragraph, Wrap},
     Frame,
 };
 
+use super::{theme::theme, Mode};
+use crate::await_modify::WatchStatus;
+
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const WATCHED_DIR_LABEL: &str = " Watched Directory:";
 
-pub fn render_header(f: &mut Frame, area: &Rect) {
+pub fn render_header(
+    f: &mut Frame,
+    area: &Rect,
+    command: &[String],
+    mode: Mode,
+    watch: &WatchStatus,
+    socket: Option<&Path>,
+) {
     let header_block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::LightGreen))
+        .border_style(Style::default().fg(theme().border))
         .border_type(ratatui::widgets::BorderType::Rounded)
         .title(format!("Await-Modify-Do v{}", APP_VERSION))
-        .title_style(Style::default().fg(Color::Yellow).bold())
+        .title_style(Style::default().fg(theme().title).bold())
         // .bg(Color::Rgb(30, 34, 42))
         .title_alignment(Alignment::Center);
+    let header_block = match socket {
+        Some(socket) => header_block.title_bottom(
+            Line::from(format!(" amdo ctl: {} ", socket.display()))
+                .right_aligned()
+                .style(Style::default().fg(theme().muted)),
+        ),
+        None => header_block,
+    };
     f.render_widget(header_block, *area);
 
     let inner_area = area.inner(Margin {
@@ -29,7 +47,11 @@ pub fn render_header(f: &mut Frame, area: &Rect) {
         vertical: 1,
     });
 
-    let lines = vec![render_watched_dir(), render_mode(), render_command()];
+    let lines = vec![
+        render_watched_dir(),
+        render_mode(mode, watch),
+        render_command(command),
+    ];
     let text = Text::from(lines);
     let p = Paragraph::new(text)
         .wrap(Wrap { trim: true })
@@ -38,29 +60,28 @@ pub fn render_header(f: &mut Frame, area: &Rect) {
     f.render_widget(p, inner_area);
 }
 
-fn render_args() -> Result<Vec<Span<'static>>, ()> {
+fn render_args(command: &[String]) -> Result<Vec<Span<'static>>, ()> {
     let mut spans = vec![];
-    match std::env::args().nth(1) {
+    match command.first().cloned() {
         Some(p) => match which::which(&p) {
-            Ok(_) => spans.push(Span::styled(p, Style::default().fg(Color::Green))),
+            Ok(_) => spans.push(Span::styled(p, Style::default().fg(theme().success))),
             Err(_) => {
                 let path = Path::new(&p);
                 if path.exists() {
                     let icon = FileIcon::from(path);
                     spans.push(Span::styled(
                         icon.to_string(),
-                        Style::default()
-                            .fg(Color::from_str(icon.color).unwrap_or(Color::LightBlue)),
+                        Style::default().fg(theme().icon(icon.color)),
                     ));
                     spans.push(Span::raw(" "));
                     spans.push(Span::styled(
                         p,
-                        Style::default().fg(Color::LightCyan).underlined(),
+                        Style::default().fg(theme().accent).underlined(),
                     ));
                 } else {
                     spans.push(Span::styled(
                         p,
-                        Style::default().fg(Color::Red).bold().underlined(),
+                        Style::default().fg(theme().error).bold().underlined(),
                     ))
                 }
             }
@@ -70,58 +91,80 @@ fn render_args() -> Result<Vec<Span<'static>>, ()> {
         }
     }
 
-    for arg in std::env::args().skip(2) {
+    for arg in command.iter().skip(1).cloned() {
         let path = Path::new(&arg);
         if arg.starts_with('-') {
             spans.push(Span::raw(" "));
-            spans.push(Span::styled(arg, Style::default().fg(Color::LightRed)));
+            spans.push(Span::styled(arg, Style::default().fg(theme().failure)));
         } else if path.is_dir() {
             spans.push(Span::raw(" "));
-            spans.push(Span::styled("", Style::default().fg(Color::LightBlue)));
+            spans.push(Span::styled("", Style::default().fg(theme().info)));
             spans.push(Span::raw(" "));
-            spans.push(Span::styled(arg, Style::default().fg(Color::LightCyan)).underlined());
+            spans.push(Span::styled(arg, Style::default().fg(theme().accent)).underlined());
         } else if path.is_file() {
             spans.push(Span::raw(" "));
             let icon = FileIcon::from(path);
-            let color = Color::from_str(icon.color).unwrap_or(Color::LightBlue);
+            let color = theme().icon(icon.color);
             spans.push(Span::styled(icon.to_string(), Style::default().fg(color)));
             spans.push(Span::raw(" "));
-            spans.push(Span::styled(arg, Style::default().fg(Color::LightCyan)).underlined());
+            spans.push(Span::styled(arg, Style::default().fg(theme().accent)).underlined());
         } else {
             spans.push(Span::raw(" "));
-            spans.push(Span::styled(arg, Style::default().fg(Color::White)));
+            spans.push(Span::styled(arg, Style::default().fg(theme().text)));
         }
     }
 
     Ok(spans)
 }
 
-fn render_command() -> Line<'static> {
+fn render_command(command: &[String]) -> Line<'static> {
     let mut spans = vec![
         Span::raw(" "),
-        Span::styled("Command:", Style::default().fg(Color::Yellow).bold()),
+        Span::styled("Command:", Style::default().fg(theme().title).bold()),
         Span::raw(" "),
-        Span::styled("$", Style::default().fg(Color::Green).bold()),
+        Span::styled("$", Style::default().fg(theme().success).bold()),
         Span::raw(" "),
     ];
-    match render_args() {
+    match render_args(command) {
         Ok(x) => {
             spans.extend(x);
         }
         Err(_) => {
-            spans.push(Span::styled("No command", Style::default().fg(Color::Red)));
+            spans.push(Span::styled(
+                "No command",
+                Style::default().fg(theme().error),
+            ));
         }
     }
-    
+
     Line::from(spans)
 }
 
-fn render_mode() -> Line<'static> {
+fn render_mode(mode: Mode, watch: &WatchStatus) -> Line<'static> {
+    let mode_span = match mode {
+        Mode::Normal if watch.paused => Span::styled(
+            format!("Paused ({} changes pending)", watch.pending),
+            Style::default().fg(theme().warning),
+        ),
+        Mode::Normal if watch.git_operation.is_some() => Span::styled(
+            format!(
+                "Waiting for git {} ({} changes pending)",
+                watch.git_operation.unwrap_or_default(),
+                watch.pending
+            ),
+            Style::default().fg(theme().warning),
+        ),
+        Mode::Normal => Span::styled("Normal", Style::default().fg(theme().border)),
+        Mode::Insert => Span::styled(
+            "Insert (Esc to leave, Ctrl+D to close input)",
+            Style::default().fg(theme().secondary),
+        ),
+    };
     Line::from(vec![
         Span::raw(" "),
-        Span::styled("Mode:", Style::default().fg(Color::Yellow).bold()),
+        Span::styled("Mode:", Style::default().fg(theme().title).bold()),
         Span::raw(" "),
-        Span::styled("Normal", Style::default().fg(Color::LightGreen)),
+        mode_span,
     ])
 }
 
@@ -130,27 +173,27 @@ fn render_watched_dir() -> Line<'static> {
         Ok(p) => {
             Line::from(vec![
                 Span::raw(" "),
-                Span::styled(WATCHED_DIR_LABEL, Style::default().fg(Color::Yellow).bold()),
+                Span::styled(WATCHED_DIR_LABEL, Style::default().fg(theme().title).bold()),
                 Span::raw(" "),
                 Span::styled(
                     // Directory icon
                     " ",
-                    Style::default().fg(Color::LightBlue),
+                    Style::default().fg(theme().info),
                 ),
                 Span::styled(
                     p.to_string_lossy().to_string(),
-                    Style::default().fg(Color::LightCyan).underlined(),
+                    Style::default().fg(theme().accent).underlined(),
                 ),
             ])
         }
 
         Err(e) => Line::from(vec![
             Span::raw(" "),
-            Span::styled(WATCHED_DIR_LABEL, Style::default().fg(Color::Yellow).bold()),
+            Span::styled(WATCHED_DIR_LABEL, Style::default().fg(theme().title).bold()),
             Span::raw(" "),
             Span::styled(
                 format!("Error getting current directory {}", e),
-                Style::default().fg(Color::Red),
+                Style::default().fg(theme().error),
             ),
         ]),
     }
@@ -0,0 +1,56 @@
+use ratatui::{
+    layout::{Alignment, Constraint, Rect},
+    style::{Style, Stylize},
+    widgets::{Block, Borders, Clear, Row, Table},
+    Frame,
+};
+
+use super::{
+    keys::{Action, KeyBindings},
+    theme::theme,
+};
+
+pub fn render_help(f: &mut Frame, area: Rect, bindings: &KeyBindings) {
+    let rows = Action::ALL
+        .iter()
+        .map(|action| {
+            Row::new(vec![
+                bindings.keys_for(*action).join(", "),
+                action.name().to_string(),
+                action.description().to_string(),
+            ])
+        })
+        .collect::<Vec<Row>>();
+
+    let width = area.width.saturating_sub(4).min(72);
+    let height = (rows.len() as u16 + 4).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width.saturating_sub(width)) / 2,
+        y: area.y + (area.height.saturating_sub(height)) / 2,
+        width,
+        height,
+    };
+    f.render_widget(Clear, popup);
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(12),
+            Constraint::Length(16),
+            Constraint::Min(0),
+        ],
+    )
+    .header(
+        Row::new(vec!["Key", "Action", "Description"]).style(Style::default().fg(theme().title)),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme().info))
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .title(" Key Bindings (Esc to close) ")
+            .title_alignment(Alignment::Center)
+            .title_style(Style::default().fg(theme().title).bold()),
+    );
+    f.render_widget(table, popup);
+}
@@ -0,0 +1,385 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::Config;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Rerun,
+    RerunFailed,
+    Cancel,
+    ClearOutput,
+    ToggleWrap,
+    ToggleStats,
+    ToggleHeader,
+    ToggleZen,
+    ToggleArrangement,
+    GrowStats,
+    ShrinkStats,
+    PauseWatching,
+    SwitchCommand,
+    InsertMode,
+    ToggleProblems,
+    NextProblem,
+    PreviousProblem,
+    OpenProblem,
+    ScrollUp,
+    ScrollDown,
+    YankOutput,
+    YankVisible,
+    YankMatches,
+    SaveOutput,
+    ShowLogPath,
+    Help,
+    Palette,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 28] = [
+        Action::Rerun,
+        Action::RerunFailed,
+        Action::Cancel,
+        Action::ClearOutput,
+        Action::ToggleWrap,
+        Action::ToggleStats,
+        Action::ToggleHeader,
+        Action::ToggleZen,
+        Action::ToggleArrangement,
+        Action::GrowStats,
+        Action::ShrinkStats,
+        Action::PauseWatching,
+        Action::SwitchCommand,
+        Action::InsertMode,
+        Action::ToggleProblems,
+        Action::NextProblem,
+        Action::PreviousProblem,
+        Action::OpenProblem,
+        Action::ScrollUp,
+        Action::ScrollDown,
+        Action::YankOutput,
+        Action::YankVisible,
+        Action::YankMatches,
+        Action::SaveOutput,
+        Action::ShowLogPath,
+        Action::Help,
+        Action::Palette,
+        Action::Quit,
+    ];
+
+    /// Name used in the config file and the command palette.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Rerun => "rerun",
+            Action::RerunFailed => "rerun-failed",
+            Action::Cancel => "cancel",
+            Action::ClearOutput => "clear-output",
+            Action::ToggleWrap => "toggle-wrap",
+            Action::ToggleStats => "toggle-stats",
+            Action::ToggleHeader => "toggle-header",
+            Action::ToggleZen => "toggle-zen",
+            Action::ToggleArrangement => "toggle-arrangement",
+            Action::GrowStats => "grow-stats",
+            Action::ShrinkStats => "shrink-stats",
+            Action::PauseWatching => "pause-watching",
+            Action::SwitchCommand => "switch-command",
+            Action::InsertMode => "insert-mode",
+            Action::ToggleProblems => "toggle-problems",
+            Action::NextProblem => "next-problem",
+            Action::PreviousProblem => "previous-problem",
+            Action::OpenProblem => "open-problem",
+            Action::ScrollUp => "scroll-up",
+            Action::ScrollDown => "scroll-down",
+            Action::YankOutput => "yank-output",
+            Action::YankVisible => "yank-visible",
+            Action::YankMatches => "yank-matches",
+            Action::SaveOutput => "save-output",
+            Action::ShowLogPath => "show-log-path",
+            Action::Help => "help",
+            Action::Palette => "palette",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Rerun => "Rerun the command",
+            Action::RerunFailed => "Rerun the failing tests, then the whole suite",
+            Action::Cancel => "Cancel the running command",
+            Action::ClearOutput => "Clear the output",
+            Action::ToggleWrap => "Toggle wrapping of long output lines",
+            Action::ToggleStats => "Show or hide the stats panel",
+            Action::ToggleHeader => "Show or hide the header",
+            Action::ToggleZen => "Show only the output, or all panels again",
+            Action::ToggleArrangement => "Put the stats panel on the side or above the output",
+            Action::GrowStats => "Grow the stats panel",
+            Action::ShrinkStats => "Shrink the stats panel",
+            Action::PauseWatching => "Pause or resume watching for changes",
+            Action::SwitchCommand => "Switch to another command",
+            Action::InsertMode => "Forward keystrokes to the command",
+            Action::ToggleProblems => "Show or hide the problems panel",
+            Action::NextProblem => "Select the next problem",
+            Action::PreviousProblem => "Select the previous problem",
+            Action::OpenProblem => "Open the selected problem in $EDITOR",
+            Action::ScrollUp => "Scroll the focused panel up a page",
+            Action::ScrollDown => "Scroll the focused panel down a page",
+            Action::YankOutput => "Copy the output to the clipboard",
+            Action::YankVisible => "Copy the visible output to the clipboard",
+            Action::YankMatches => "Copy the output lines containing a text to the clipboard",
+            Action::SaveOutput => "Save the output to a file",
+            Action::ShowLogPath => "Show the log file of the current run",
+            Action::Help => "Show the key bindings",
+            Action::Palette => "Open the command palette",
+            Action::Quit => "Quit",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Action::Rerun => &["space"],
+            Action::RerunFailed => &["f"],
+            Action::Cancel => &["esc"],
+            Action::ClearOutput => &["c"],
+            Action::ToggleWrap => &["w"],
+            Action::ToggleStats => &["s"],
+            Action::ToggleHeader => &["h"],
+            Action::ToggleZen => &["z"],
+            Action::ToggleArrangement => &["o"],
+            Action::GrowStats => &["+"],
+            Action::ShrinkStats => &["-"],
+            Action::PauseWatching => &["p"],
+            Action::SwitchCommand => &[],
+            Action::InsertMode => &["i"],
+            Action::ToggleProblems => &["d"],
+            Action::NextProblem => &["down", "j"],
+            Action::PreviousProblem => &["up", "k"],
+            Action::OpenProblem => &["enter"],
+            Action::ScrollUp => &["pageup"],
+            Action::ScrollDown => &["pagedown"],
+            Action::YankOutput => &["y"],
+            Action::YankVisible => &["Y"],
+            Action::YankMatches => &["/"],
+            Action::SaveOutput => &["ctrl+s"],
+            Action::ShowLogPath => &["l"],
+            Action::Help => &["?"],
+            Action::Palette => &[":"],
+            Action::Quit => &["q"],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    fn from_event(event: &KeyEvent) -> Key {
+        let mut modifiers = event.modifiers;
+        // Shift is already part of the character, e.g. `?` or `R`
+        if let KeyCode::Char(_) = event.code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Key {
+            code: event.code,
+            modifiers,
+        }
+    }
+
+    fn parse(spec: &str) -> Result<Key, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts = spec.split('+').collect::<Vec<&str>>();
+        // `+` on its own or as the last key, e.g. `ctrl++`
+        let key = match spec.ends_with("++") || spec == "+" {
+            true => {
+                parts.truncate(parts.len().saturating_sub(2));
+                "+"
+            }
+            false => parts.pop().unwrap_or_default(),
+        };
+        for modifier in parts {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers |= KeyModifiers::CONTROL,
+                "alt" => modifiers |= KeyModifiers::ALT,
+                "shift" => modifiers |= KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier {:?} in key {:?}", modifier, spec)),
+            }
+        }
+
+        let code = match key.to_lowercase().as_str() {
+            "space" => KeyCode::Char(' '),
+            "comma" => KeyCode::Char(','),
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f if f.len() > 1 && f.starts_with('f') && f[1..].parse::<u8>().is_ok() => {
+                KeyCode::F(f[1..].parse().unwrap())
+            }
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(format!("Unknown key {:?}", spec)),
+                }
+            }
+        };
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Ok(Key { code, modifiers })
+    }
+
+    fn display(&self) -> String {
+        let mut s = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            s.push_str("Ctrl+");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            s.push_str("Alt+");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            s.push_str("Shift+");
+        }
+        match self.code {
+            KeyCode::Char(' ') => s.push_str("Space"),
+            KeyCode::Char(c) => s.push(c),
+            KeyCode::F(n) => s.push_str(&format!("F{}", n)),
+            code => s.push_str(&format!("{:?}", code)),
+        }
+        s
+    }
+}
+
+pub struct KeyBindings {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl KeyBindings {
+    /// Default bindings, where every action listed in the `[keys]` section of the config
+    /// replaces the default keys of that action, e.g. `rerun = space, r`.
+    pub fn load(config: &Config) -> Result<KeyBindings, String> {
+        let overrides = config.section("keys");
+        for (name, _) in overrides {
+            if Action::from_name(name).is_none() {
+                return Err(format!("Unknown action {:?} in [keys]", name));
+            }
+        }
+
+        let mut bindings = vec![];
+        for action in Action::ALL {
+            let keys = match config.get("keys", action.name()) {
+                Some(keys) => keys
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|k| !k.is_empty())
+                    .collect::<Vec<&str>>(),
+                None => action.default_keys().to_vec(),
+            };
+            for key in keys {
+                bindings.push((Key::parse(key)?, action));
+            }
+        }
+        Ok(KeyBindings { bindings })
+    }
+
+    pub fn action_for(&self, event: &KeyEvent) -> Option<Action> {
+        let key = Key::from_event(event);
+        self.bindings
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, a)| *a == action)
+            .map(|(k, _)| k.display())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Result<Key, String> {
+        Ok(Key { code, modifiers })
+    }
+
+    #[test]
+    fn parses_named_keys() {
+        assert_eq!(
+            Key::parse("space"),
+            key(KeyCode::Char(' '), KeyModifiers::NONE)
+        );
+        assert_eq!(Key::parse("Esc"), key(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(
+            Key::parse("pagedown"),
+            key(KeyCode::PageDown, KeyModifiers::NONE)
+        );
+        assert_eq!(Key::parse("f5"), key(KeyCode::F(5), KeyModifiers::NONE));
+        assert_eq!(
+            Key::parse("comma"),
+            key(KeyCode::Char(','), KeyModifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        assert_eq!(
+            Key::parse("ctrl+s"),
+            key(KeyCode::Char('s'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Key::parse("Control+Alt+enter"),
+            key(KeyCode::Enter, KeyModifiers::CONTROL | KeyModifiers::ALT)
+        );
+        assert_eq!(
+            Key::parse("shift+tab"),
+            key(KeyCode::Tab, KeyModifiers::SHIFT)
+        );
+        // Shift is part of the character
+        assert_eq!(
+            Key::parse("shift+R"),
+            key(KeyCode::Char('R'), KeyModifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn parses_plus_key() {
+        assert_eq!(Key::parse("+"), key(KeyCode::Char('+'), KeyModifiers::NONE));
+        assert_eq!(
+            Key::parse("ctrl++"),
+            key(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Key::parse("hyper+a").is_err());
+        assert!(Key::parse("ab").is_err());
+        assert!(Key::parse("ctrl+").is_err());
+        assert!(Key::parse("").is_err());
+    }
+
+    #[test]
+    fn matches_events_regardless_of_shift_on_characters() {
+        let event = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::SHIFT);
+        assert_eq!(Key::from_event(&event), Key::parse("?").unwrap());
+    }
+}
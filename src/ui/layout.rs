@@ -0,0 +1,256 @@
+use ratatui::layout::{Constraint, Layout, Rect};
+
+use crate::config::Config;
+
+const HEADER_HEIGHT: u16 = 5;
+const DEFAULT_STATS_WIDTH: u16 = 60;
+/// The borders and one row or a label and a gauge
+const MIN_STATS_HEIGHT: u16 = 3;
+const MIN_STATS_WIDTH: u16 = 20;
+/// Columns the stats panel is resized by at a time on the side, where it is resized by one row
+/// at a time above or below the output
+const WIDTH_STEP: u16 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Panel {
+    Header,
+    Stats,
+    Output,
+}
+
+impl Panel {
+    fn parse(name: &str) -> Option<Panel> {
+        match name {
+            "header" => Some(Panel::Header),
+            "stats" => Some(Panel::Stats),
+            "output" => Some(Panel::Output),
+            _ => None,
+        }
+    }
+}
+
+/// How the stats panel and the output are arranged, the header always spans the whole width.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Arrangement {
+    /// The stats panel above or below the output
+    Vertical,
+    /// The stats panel on the side of the output
+    Horizontal,
+}
+
+/// Areas of the panels, empty for hidden panels.
+#[derive(Clone, Copy, Default)]
+pub struct PanelAreas {
+    pub header: Rect,
+    pub stats: Rect,
+    pub output: Rect,
+}
+
+/// Order, visibility and size of the panels, read from the `[layout]` section of the config and
+/// changed at runtime.
+pub struct PanelLayout {
+    order: Vec<Panel>,
+    pub arrangement: Arrangement,
+    pub show_header: bool,
+    pub show_stats: bool,
+    /// Only the output, taking the whole screen
+    pub zen: bool,
+    /// Rows of the stats panel when arranged vertically, `None` to fit its content
+    stats_height: Option<u16>,
+    /// Columns of the stats panel when arranged horizontally
+    stats_width: u16,
+}
+
+impl PanelLayout {
+    pub fn load(config: &Config) -> Result<PanelLayout, String> {
+        for (key, _) in config.section("layout") {
+            if ![
+                "order",
+                "arrangement",
+                "hide",
+                "stats_height",
+                "stats_width",
+            ]
+            .contains(&key.as_str())
+            {
+                return Err(format!("Unknown setting {:?} in [layout]", key));
+            }
+        }
+        let panels = |key: &str| -> Result<Vec<Panel>, String> {
+            config
+                .get("layout", key)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    Panel::parse(name).ok_or_else(|| {
+                        format!(
+                            "Expected header, stats or output for {} in [layout], got {:?}",
+                            key, name
+                        )
+                    })
+                })
+                .collect()
+        };
+        // Panels left out of the order keep their place after the listed ones
+        let mut order = vec![];
+        let listed = panels("order")?;
+        for panel in listed
+            .into_iter()
+            .chain([Panel::Header, Panel::Stats, Panel::Output])
+        {
+            if !order.contains(&panel) {
+                order.push(panel);
+            }
+        }
+        let hidden = panels("hide")?;
+        let arrangement = match config.get("layout", "arrangement") {
+            None | Some("vertical") => Arrangement::Vertical,
+            Some("horizontal") => Arrangement::Horizontal,
+            Some(value) => {
+                return Err(format!(
+                    "Expected vertical or horizontal for arrangement in [layout], got {:?}",
+                    value
+                ))
+            }
+        };
+        let size = |key: &str, min: u16| -> Result<Option<u16>, String> {
+            config
+                .get("layout", key)
+                .map(|value| {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|size| *size >= min)
+                        .ok_or_else(|| {
+                            format!(
+                                "Expected a number of at least {} for {} in [layout], got {:?}",
+                                min, key, value
+                            )
+                        })
+                })
+                .transpose()
+        };
+        Ok(PanelLayout {
+            order,
+            arrangement,
+            show_header: !hidden.contains(&Panel::Header),
+            show_stats: !hidden.contains(&Panel::Stats),
+            zen: false,
+            stats_height: size("stats_height", MIN_STATS_HEIGHT)?,
+            stats_width: size("stats_width", MIN_STATS_WIDTH)?.unwrap_or(DEFAULT_STATS_WIDTH),
+        })
+    }
+
+    /// Splits `area` into the panels, where `content_height` is the height the stats panel
+    /// needs to show all its rows.
+    pub fn areas(&self, area: Rect, content_height: u16) -> PanelAreas {
+        let mut areas = PanelAreas {
+            header: Rect::default(),
+            stats: Rect::default(),
+            output: area,
+        };
+        if self.zen {
+            return areas;
+        }
+        let visible = self
+            .order
+            .iter()
+            .copied()
+            .filter(|panel| match panel {
+                Panel::Header => self.show_header,
+                Panel::Stats => self.show_stats,
+                Panel::Output => true,
+            })
+            .collect::<Vec<Panel>>();
+        match self.arrangement {
+            Arrangement::Vertical => {
+                let constraints = visible.iter().map(|panel| match panel {
+                    Panel::Header => Constraint::Length(HEADER_HEIGHT),
+                    Panel::Stats => Constraint::Length(self.stats_height(content_height)),
+                    Panel::Output => Constraint::Min(0),
+                });
+                let rects = Layout::vertical(constraints).split(area);
+                for (panel, rect) in visible.iter().zip(rects.iter()) {
+                    *areas.area_mut(*panel) = *rect;
+                }
+            }
+            Arrangement::Horizontal => {
+                // The stats panel and the output share the rows not taken by the header
+                // The header goes below the others when it is listed last
+                let header_last = visible.last() == Some(&Panel::Header);
+                let constraints = match (self.show_header, header_last) {
+                    (false, _) => vec![Constraint::Min(0)],
+                    (true, false) => vec![Constraint::Length(HEADER_HEIGHT), Constraint::Min(0)],
+                    (true, true) => vec![Constraint::Min(0), Constraint::Length(HEADER_HEIGHT)],
+                };
+                let rects = Layout::vertical(constraints).split(area);
+                let body = match (self.show_header, header_last) {
+                    (false, _) => rects[0],
+                    (true, false) => {
+                        areas.header = rects[0];
+                        rects[1]
+                    }
+                    (true, true) => {
+                        areas.header = rects[1];
+                        rects[0]
+                    }
+                };
+                let side = visible
+                    .iter()
+                    .copied()
+                    .filter(|panel| *panel != Panel::Header)
+                    .collect::<Vec<Panel>>();
+                let constraints = side.iter().map(|panel| match panel {
+                    Panel::Stats => Constraint::Length(self.stats_width),
+                    _ => Constraint::Min(0),
+                });
+                let rects = Layout::horizontal(constraints).split(body);
+                for (panel, rect) in side.iter().zip(rects.iter()) {
+                    *areas.area_mut(*panel) = *rect;
+                }
+            }
+        }
+        areas
+    }
+
+    pub fn toggle_arrangement(&mut self) {
+        self.arrangement = match self.arrangement {
+            Arrangement::Vertical => Arrangement::Horizontal,
+            Arrangement::Horizontal => Arrangement::Vertical,
+        };
+    }
+
+    /// Grows the stats panel by one step, or shrinks it with a negative `steps`.
+    pub fn resize_stats(&mut self, steps: i16, content_height: u16) {
+        match self.arrangement {
+            Arrangement::Vertical => {
+                let height = self
+                    .stats_height(content_height)
+                    .saturating_add_signed(steps);
+                self.stats_height = Some(height.max(MIN_STATS_HEIGHT));
+            }
+            Arrangement::Horizontal => {
+                let width = self
+                    .stats_width
+                    .saturating_add_signed(steps * WIDTH_STEP as i16);
+                self.stats_width = width.max(MIN_STATS_WIDTH);
+            }
+        }
+    }
+
+    fn stats_height(&self, content_height: u16) -> u16 {
+        self.stats_height.unwrap_or(content_height)
+    }
+}
+
+impl PanelAreas {
+    fn area_mut(&mut self, panel: Panel) -> &mut Rect {
+        match panel {
+            Panel::Header => &mut self.header,
+            Panel::Stats => &mut self.stats,
+            Panel::Output => &mut self.output,
+        }
+    }
+}
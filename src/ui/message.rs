@@ -0,0 +1,40 @@
+use ratatui::{
+    layout::{Alignment, Rect},
+    style::{Style, Stylize},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+
+use super::theme::theme;
+
+/// Popup with a short message, closed by any key.
+pub fn render_message(f: &mut Frame, area: Rect, title: &str, text: &str) {
+    let width = area.width.saturating_sub(4).min(80);
+    let inner_width = width.saturating_sub(4).max(1) as usize;
+    let lines = text
+        .lines()
+        .map(|l| l.chars().count().div_ceil(inner_width).max(1))
+        .sum::<usize>() as u16;
+    let height = (lines + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width.saturating_sub(width)) / 2,
+        y: area.y + (area.height.saturating_sub(height)) / 2,
+        width,
+        height,
+    };
+    f.render_widget(Clear, popup);
+
+    let paragraph = Paragraph::new(text.to_string())
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme().info))
+                .border_type(ratatui::widgets::BorderType::Rounded)
+                .padding(ratatui::widgets::Padding::horizontal(1))
+                .title(format!(" {} ", title))
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(theme().title).bold()),
+        );
+    f.render_widget(paragraph, popup);
+}
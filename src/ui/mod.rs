@@ -1,14 +1,25 @@
 use std::{
-    io::{stdout, Result},
-    rc::Rc,
+    io::{stdout, Error, Result, Write},
+    path::PathBuf,
 };
 
 use crossbeam::channel::{Receiver, Sender};
+use durations::RunDurations;
 use header::render_header;
-use output::render_output;
+use help::render_help;
+use keys::{Action, KeyBindings};
+use layout::{PanelAreas, PanelLayout};
+use message::render_message;
+use mouse::{handle_mouse, screen_rows};
+use output::{render_output, RenderedOutput};
+use palette::{render_palette, Palette};
+use problems::{render_problems, Problems};
 use ratatui::{
     crossterm::{
-        event::{self, KeyCode, KeyEventKind},
+        event::{
+            self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEvent, KeyEventKind,
+            KeyModifiers,
+        },
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
         ExecutableCommand,
     },
@@ -16,109 +27,552 @@ use ratatui::{
     prelude::CrosstermBackend,
     Terminal,
 };
+use sampler::{Sample, Sampler};
 use stats::render_stats;
-use sysinfo::System;
+use theme::Theme;
 
-use crate::process_manager::ProcessExecution;
+use crate::{
+    ansi::plain_lines,
+    await_modify::WatchControl,
+    config::Config,
+    hooks::Hooks,
+    notifications,
+    process_manager::{parse_command, shell_quote, ProcessExecution},
+    test_results::Location,
+};
 
+mod diagnostics;
+mod durations;
+mod editor;
 mod header;
+mod help;
+mod input;
+mod keys;
+mod layout;
+mod message;
+mod mouse;
 mod output;
+mod palette;
+mod problems;
+mod sampler;
 mod stats;
+mod theme;
+mod yank;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    /// Keystrokes are forwarded to stdin of the running process
+    Insert,
+}
 
-fn make_panels_rect(area: Rect) -> Rc<[Rect]> {
-    let chunks = Layout::default()
-        .direction(ratatui::layout::Direction::Vertical)
-        .constraints(
-            [
-                Constraint::Length(5),
-                Constraint::Length(5),
-                Constraint::Min(0),
-            ]
-            .as_ref(),
-        )
-        .split(area);
-    chunks
+enum Overlay {
+    None,
+    Help,
+    Palette(Palette),
+    Message { title: String, text: String },
 }
 
-pub fn init(rx_pm: Receiver<ProcessExecution>, manual_trigger_tx: Sender<()>) -> Result<()> {
+/// Panel that scrolls with the scroll keys.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Output,
+    Problems,
+}
+
+struct App {
+    execution: Option<ProcessExecution>,
+    command: Vec<String>,
+    mode: Mode,
+    overlay: Overlay,
+    wrap: bool,
+    layout: PanelLayout,
+    focus: Focus,
+    /// First output line shown when scrolled up, `None` to follow the end of the output
+    output_scroll: Option<usize>,
+    /// Where the panels were drawn in the last frame, for the mouse
+    areas: PanelAreas,
+    problems_area: Rect,
+    output: RenderedOutput,
+    /// Text of the output rows as drawn in the last frame
+    output_rows: Vec<String>,
+    problems: Problems,
+    show_problems: bool,
+    durations: RunDurations,
+    /// Location to open in the editor once the frame is drawn
+    pending_edit: Option<Location>,
+    /// Working directory of the command, which paths in its output are relative to
+    cwd: Option<PathBuf>,
+    /// Whether the command can be typed into in insert mode
+    interactive: bool,
+    /// Whether the command runs with a shell, which commands switched to are checked for
+    shell: bool,
+    bindings: KeyBindings,
+    /// To cancel a hook that is being waited for
+    hooks: Hooks,
+    manual_trigger_tx: Sender<()>,
+    rerun_failed_tx: Sender<()>,
+    switch_command_tx: Sender<Vec<String>>,
+    watch: WatchControl,
+}
+
+/// Channels and handles the TUI uses to follow and control amdo.
+pub struct Context {
+    pub rx_pm: Receiver<ProcessExecution>,
+    pub manual_trigger_tx: Sender<()>,
+    pub rerun_failed_tx: Sender<()>,
+    pub switch_command_tx: Sender<Vec<String>>,
+    pub watch: WatchControl,
+    pub command: Vec<String>,
+    /// Control socket shown in the header
+    pub socket: Option<PathBuf>,
+    pub hooks: Hooks,
+    pub cwd: Option<PathBuf>,
+    pub interactive: bool,
+    pub shell: bool,
+}
+
+pub fn init(context: Context, config: &Config) -> Result<()> {
+    let Context {
+        rx_pm,
+        manual_trigger_tx,
+        rerun_failed_tx,
+        switch_command_tx,
+        watch,
+        command,
+        socket,
+        hooks,
+        cwd,
+        interactive,
+        shell,
+    } = context;
+    let bindings = KeyBindings::load(config).map_err(Error::other)?;
+    let sampler = Sampler::spawn(config).map_err(Error::other)?;
+    let layout = PanelLayout::load(config).map_err(Error::other)?;
+    theme::init(Theme::load(config).map_err(Error::other)?);
+
     stdout().execute(EnterAlternateScreen)?;
+    stdout().execute(EnableMouseCapture)?;
     enable_raw_mode()?;
     let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
     terminal.clear()?;
 
-    let mut currrent_execution: Option<ProcessExecution> = None;
-    let mut system = System::new_all();
-    let current_pid = sysinfo::get_current_pid().expect("Could not get current pid");
-    let parrent_pid = system
-        .process(current_pid)
-        .expect("Could not find information for current pid")
-        .parent()
-        .expect("Current program doesn't have a parent process, which it is designed to have using the shell");
+    let mut app = App {
+        execution: None,
+        command,
+        mode: Mode::Normal,
+        overlay: Overlay::None,
+        wrap: false,
+        layout,
+        focus: Focus::Output,
+        output_scroll: None,
+        areas: PanelAreas::default(),
+        problems_area: Rect::default(),
+        output: RenderedOutput::default(),
+        output_rows: vec![],
+        problems: Problems::default(),
+        show_problems: true,
+        durations: RunDurations::default(),
+        pending_edit: None,
+        cwd,
+        interactive,
+        shell,
+        bindings,
+        hooks: hooks.clone(),
+        manual_trigger_tx,
+        rerun_failed_tx,
+        switch_command_tx,
+        watch,
+    };
+    let mut sample = Sample::default();
 
     loop {
         if let Ok(execution) = rx_pm.try_recv() {
-            if let Some(mut exe) = currrent_execution {
-                exe.child.kill().expect("Could not kill the process");
+            if let Some(exe) = app.execution {
+                exe.kill().expect("Could not kill the process");
             }
-            currrent_execution = Some(execution);
+            sampler.follow(execution.clone());
+            app.execution = Some(execution);
+            app.output_scroll = None;
+        }
+        // Only the latest sample is shown
+        while let Ok(latest) = sampler.rx.try_recv() {
+            sample = latest;
         }
         terminal.draw(|frame| {
-            let areas = make_panels_rect(frame.area());
-            let [header_area, stats_area, output_area] = areas.as_ref() else {
-                panic!("Could not get the areas for the panels");
+            if let Some(exe) = &app.execution {
+                app.problems.update(exe);
+                app.durations.record(exe);
+            }
+            let areas = app.layout.areas(frame.area(), app.stats_height());
+            let watch_status = app.watch.status();
+            if !areas.header.is_empty() {
+                render_header(
+                    frame,
+                    &areas.header,
+                    &app.command,
+                    app.mode,
+                    &watch_status,
+                    socket.as_deref(),
+                );
+            }
+            if !areas.stats.is_empty() {
+                render_stats(
+                    frame,
+                    &areas.stats,
+                    &sample,
+                    &mut app.execution,
+                    &watch_status,
+                    (!hooks.is_empty()).then(|| hooks.results()),
+                    app.problems.tests.as_ref(),
+                );
+            }
+            let problems_height = match app.show_problems && !app.layout.zen {
+                true => app.problems.height().min(areas.output.height / 2),
+                false => 0,
             };
-            render_header(frame, header_area);
-            render_stats(
+            let [output_area, problems_area] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(problems_height)])
+                    .areas(areas.output);
+            app.output = render_output(
                 frame,
-                stats_area,
-                &mut system,
-                &mut currrent_execution,
-                &parrent_pid,
+                &output_area,
+                &mut app.execution,
+                app.wrap,
+                app.output_scroll,
+                &app.problems,
+                &app.durations,
             );
-            render_output(frame, output_area, &mut currrent_execution);
+            app.output_rows = screen_rows(frame.buffer_mut(), app.output.text_area);
+            if problems_height > 0 {
+                let focused = app.focus == Focus::Problems;
+                render_problems(frame, problems_area, &app.problems, focused);
+            }
+            app.areas = areas;
+            app.problems_area = problems_area;
+            match &app.overlay {
+                Overlay::None => {}
+                Overlay::Help => render_help(frame, frame.area(), &app.bindings),
+                Overlay::Palette(palette) => render_palette(frame, frame.area(), palette),
+                Overlay::Message { title, text } => {
+                    render_message(frame, frame.area(), title, text)
+                }
+            }
         })?;
+        let notification = notifications::take_pending();
+        if !notification.is_empty() {
+            let backend = terminal.backend_mut();
+            backend.write_all(notification.as_bytes())?;
+            backend.flush()?;
+        }
 
         // Interaction to modify state -> Move to eventual ux module
         if event::poll(std::time::Duration::from_millis(16))? {
-            if let event::Event::Key(key) = event::read()? {
-                if let (KeyEventKind::Press, KeyCode::Char('q')) = (key.kind, key.code) {
-                    if let Some(ref mut exe) = currrent_execution {
-                        exe.child.kill().expect("Could not kill the process");
-                    }
-                    break;
-                }
-                // If escape is pressed, kill the current process
-                if let (KeyEventKind::Press, KeyCode::Esc) = (key.kind, key.code) {
-                    if let Some(ref mut exe) = currrent_execution {
-                        if exe
-                            .child
-                            .try_wait()
-                            .expect("Could not get the status of the process")
-                            .is_none()
-                        {
-                            exe.child.kill().expect("Could not kill the process");
-                            exe.cancelled = true;
+            match event::read()? {
+                event::Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    let action = match app.overlay {
+                        Overlay::Help | Overlay::Message { .. } => {
+                            app.overlay = Overlay::None;
+                            None
+                        }
+                        Overlay::Palette(ref mut palette) => handle_palette_key(palette, key),
+                        Overlay::None if app.mode == Mode::Insert => {
+                            handle_insert_key(&mut app, key);
+                            None
+                        }
+                        Overlay::None => app.bindings.action_for(&key),
+                    };
+                    if let Some(action) = action {
+                        if !app.perform(action) {
+                            break;
                         }
                     }
                 }
-                // If space rerun
-                if let (KeyEventKind::Press, KeyCode::Char(' ')) = (key.kind, key.code) {
-                    if let Some(ref mut exe) = currrent_execution {
-                        exe.child.kill().expect("Could not kill the process");
-                        exe.cancelled = true;
-                    }
-                    manual_trigger_tx
-                        .send(())
-                        .expect("Could not send manual trigger");
+                // The panels are covered by overlays
+                event::Event::Mouse(mouse) if matches!(app.overlay, Overlay::None) => {
+                    handle_mouse(&mut app, mouse)
+                }
+                _ => {}
+            }
+            if let Some(location) = app.pending_edit.take() {
+                let opened = editor::open_in_editor(&mut terminal, &location, app.cwd.as_deref());
+                if let Err(e) = opened {
+                    app.show_message("Open Problem", e.to_string());
                 }
             }
         }
     }
 
+    stdout().execute(DisableMouseCapture)?;
     stdout().execute(LeaveAlternateScreen)?;
     disable_raw_mode()?;
-    if let Some(mut exe) = currrent_execution {
-        exe.child.kill().expect("Could not kill the process");
+    if let Some(exe) = app.execution {
+        exe.kill().expect("Could not kill the process");
     }
     Ok(())
 }
+
+fn handle_insert_key(app: &mut App, key: KeyEvent) {
+    match (key.code, key.modifiers) {
+        (KeyCode::Esc, _) => app.mode = Mode::Normal,
+        (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
+            if let Some(ref mut exe) = app.execution {
+                exe.close_input();
+            }
+        }
+        _ => {
+            if let (Some(ref mut exe), Some(bytes)) = (&mut app.execution, input::key_to_bytes(key))
+            {
+                // The process may have exited or closed its input
+                let _ = exe.write_input(&bytes);
+            }
+        }
+    }
+}
+
+/// Edits the palette with the key and returns the action to perform when one is chosen.
+fn handle_palette_key(palette: &mut Palette, key: KeyEvent) -> Option<Action> {
+    match key.code {
+        KeyCode::Esc => Some(Action::Palette),
+        KeyCode::Enter => palette.prompt.or_else(|| palette.selected()),
+        KeyCode::Tab => {
+            palette.complete();
+            None
+        }
+        KeyCode::Down => {
+            palette.select_next();
+            None
+        }
+        KeyCode::Up => {
+            palette.select_previous();
+            None
+        }
+        KeyCode::Backspace => {
+            palette.pop();
+            None
+        }
+        KeyCode::Char(c) => {
+            palette.push(c);
+            None
+        }
+        _ => None,
+    }
+}
+
+/// The input of the prompt when the overlay is the prompt of `action` being submitted.
+fn prompt_input(overlay: Overlay, action: Action) -> Option<String> {
+    match overlay {
+        Overlay::Palette(Palette {
+            prompt: Some(prompt),
+            input,
+            ..
+        }) if prompt == action => Some(input),
+        _ => None,
+    }
+}
+
+impl App {
+    /// Performs the action, returns false when the application should quit.
+    fn perform(&mut self, action: Action) -> bool {
+        let overlay = std::mem::replace(&mut self.overlay, Overlay::None);
+        match action {
+            Action::Quit => {
+                if let Some(ref mut exe) = self.execution {
+                    exe.kill().expect("Could not kill the process");
+                }
+                return false;
+            }
+            Action::Cancel => {
+                if let Some(ref mut exe) = self.execution {
+                    if exe
+                        .try_wait()
+                        .expect("Could not get the status of the process")
+                        .is_none()
+                    {
+                        exe.cancel().expect("Could not kill the process");
+                    }
+                }
+                self.hooks.cancel();
+            }
+            Action::Rerun => {
+                self.kill_current();
+                // Full when a rerun is pending already, e.g. while a hook runs
+                let _ = self.manual_trigger_tx.try_send(());
+            }
+            Action::RerunFailed => {
+                self.kill_current();
+                let _ = self.rerun_failed_tx.try_send(());
+            }
+            Action::ClearOutput => {
+                if let Some(ref exe) = self.execution {
+                    exe.output.lock().unwrap().clear();
+                }
+            }
+            Action::ToggleWrap => self.wrap = !self.wrap,
+            Action::ToggleStats => self.layout.show_stats = !self.layout.show_stats,
+            Action::ToggleHeader => self.layout.show_header = !self.layout.show_header,
+            Action::ToggleZen => self.layout.zen = !self.layout.zen,
+            Action::ToggleArrangement => self.layout.toggle_arrangement(),
+            Action::GrowStats => self.layout.resize_stats(1, self.stats_height()),
+            Action::ShrinkStats => self.layout.resize_stats(-1, self.stats_height()),
+            Action::PauseWatching => self.watch.toggle_pause(),
+            Action::SwitchCommand => match prompt_input(overlay, action) {
+                Some(input) => match parse_command(&input, self.shell, self.cwd.as_deref()) {
+                    Ok(command) => {
+                        self.kill_current();
+                        self.command = command.clone();
+                        self.switch_command_tx
+                            .send(command)
+                            .expect("Could not send command switch");
+                    }
+                    Err(e) => self.show_message("Switch Command", e),
+                },
+                None => {
+                    let command = match self.shell {
+                        true => self.command.join(" "),
+                        false => self
+                            .command
+                            .iter()
+                            .map(|arg| shell_quote(arg))
+                            .collect::<Vec<String>>()
+                            .join(" "),
+                    };
+                    self.overlay = Overlay::Palette(Palette::prompt(action, command))
+                }
+            },
+            Action::InsertMode => match self.interactive {
+                true => self.mode = Mode::Insert,
+                false => self.show_message(
+                    "Insert Mode",
+                    "The command reads no input, start amdo with --interactive to type into it"
+                        .to_string(),
+                ),
+            },
+            Action::ToggleProblems => self.show_problems = !self.show_problems,
+            Action::NextProblem => self.problems.select_next(),
+            Action::PreviousProblem => self.problems.select_previous(),
+            Action::OpenProblem => self.pending_edit = self.problems.selected_location(),
+            Action::ScrollUp | Action::ScrollDown => {
+                let up = action == Action::ScrollUp;
+                match self.focus {
+                    Focus::Output => {
+                        let page = self.output.shown.max(1) as isize;
+                        self.scroll_output(if up { -page } else { page });
+                    }
+                    Focus::Problems if up => self.problems.select_previous(),
+                    Focus::Problems => self.problems.select_next(),
+                }
+            }
+            Action::YankOutput => {
+                let lines = self.output_lines();
+                self.yank(lines);
+            }
+            Action::YankVisible => {
+                let mut lines = self.output_lines();
+                // The output panel skips empty lines
+                lines.retain(|l| !l.is_empty());
+                let lines = lines
+                    .into_iter()
+                    .skip(self.output.first)
+                    .take(self.output.shown)
+                    .collect();
+                self.yank(lines);
+            }
+            Action::YankMatches => match prompt_input(overlay, action) {
+                Some(text) => {
+                    let mut lines = self.output_lines();
+                    lines.retain(|l| l.contains(&text));
+                    self.yank(lines);
+                }
+                None => self.overlay = Overlay::Palette(Palette::prompt(action, String::new())),
+            },
+            Action::SaveOutput => match prompt_input(overlay, action) {
+                Some(path) => {
+                    let lines = self.output_lines();
+                    let text = match std::fs::write(&path, lines.join("\n") + "\n") {
+                        Ok(_) => format!("Saved {} lines to {}", lines.len(), path),
+                        Err(e) => format!("Could not save the output to {}: {}", path, e),
+                    };
+                    self.show_message("Save Output", text);
+                }
+                None => {
+                    self.overlay =
+                        Overlay::Palette(Palette::prompt(action, "amdo-output.txt".to_string()))
+                }
+            },
+            Action::ShowLogPath => {
+                let text = match self.execution.as_ref().map(|exe| &exe.log_path) {
+                    Some(Some(path)) => path.display().to_string(),
+                    Some(None) => {
+                        "Runs are not logged, start amdo with --log-dir <dir>".to_string()
+                    }
+                    None => "Nothing has run yet".to_string(),
+                };
+                self.show_message("Log File", text);
+            }
+            Action::Help => {
+                if !matches!(overlay, Overlay::Help) {
+                    self.overlay = Overlay::Help;
+                }
+            }
+            Action::Palette => {
+                if !matches!(overlay, Overlay::Palette(_)) {
+                    self.overlay = Overlay::Palette(Palette::new());
+                }
+            }
+        }
+        true
+    }
+
+    /// Height the stats panel needs, with a row for the test summary when there are tests.
+    fn stats_height(&self) -> u16 {
+        match &self.problems.tests {
+            None => 6,
+            Some(_) => 7,
+        }
+    }
+
+    /// Scrolls the output down by `lines`, or up when negative, following the end of the output
+    /// again once scrolled to the bottom.
+    fn scroll_output(&mut self, lines: isize) {
+        let bottom = self.output.total.saturating_sub(self.output.shown);
+        let first = self
+            .output_scroll
+            .unwrap_or(self.output.first)
+            .saturating_add_signed(lines);
+        self.output_scroll = (first < bottom).then_some(first);
+    }
+
+    /// Output of the current execution without escape codes and the trailing newline.
+    fn output_lines(&self) -> Vec<String> {
+        let Some(exe) = &self.execution else {
+            return vec![];
+        };
+        let mut lines = plain_lines(&exe.output.lock().unwrap());
+        if lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+
+    fn yank(&mut self, lines: Vec<String>) {
+        let text = match yank::copy_to_clipboard(&lines.join("\n")) {
+            Ok(_) => format!("Copied {} lines to the clipboard", lines.len()),
+            Err(e) => format!("Could not copy to the clipboard: {}", e),
+        };
+        self.show_message("Yank", text);
+    }
+
+    fn show_message(&mut self, title: &str, text: String) {
+        self.overlay = Overlay::Message {
+            title: title.to_string(),
+            text,
+        };
+    }
+
+    fn kill_current(&mut self) {
+        if let Some(ref mut exe) = self.execution {
+            exe.cancel().expect("Could not kill the process");
+        }
+    }
+}
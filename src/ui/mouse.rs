@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{MouseButton, MouseEvent, MouseEventKind},
+    layout::{Margin, Position, Rect},
+};
+
+use super::{keys::Action, problems::Problems, App, Focus};
+use crate::test_results::Location;
+
+/// Lines scrolled by a step of the mouse wheel
+const WHEEL_LINES: isize = 3;
+
+/// Clicking focuses the panel, opens the clicked problem or file location in `$EDITOR` and
+/// reruns the command on the status title. The wheel scrolls the panel under the pointer.
+pub fn handle_mouse(app: &mut App, event: MouseEvent) {
+    let position = Position::new(event.column, event.row);
+    let in_problems = app.problems_area.contains(position);
+    let in_output = app.areas.output.contains(position) && !in_problems;
+    match event.kind {
+        MouseEventKind::ScrollUp if in_problems => app.problems.select_previous(),
+        MouseEventKind::ScrollDown if in_problems => app.problems.select_next(),
+        MouseEventKind::ScrollUp if in_output => app.scroll_output(-WHEEL_LINES),
+        MouseEventKind::ScrollDown if in_output => app.scroll_output(WHEEL_LINES),
+        MouseEventKind::Down(MouseButton::Left) if in_problems => {
+            app.focus = Focus::Problems;
+            let list = app.problems_area.inner(Margin::new(1, 1));
+            let row = position.y.saturating_sub(list.y) as usize;
+            if list.contains(position) && app.problems.select_row(row, list.height as usize) {
+                app.perform(Action::OpenProblem);
+            }
+        }
+        MouseEventKind::Down(MouseButton::Left) if in_output => {
+            app.focus = Focus::Output;
+            let text_area = app.output.text_area;
+            if app.output.status_title.contains(position) {
+                app.perform(Action::Rerun);
+            } else if let Some(row) = app
+                .output_rows
+                .get(position.y.saturating_sub(text_area.y) as usize)
+                .filter(|_| text_area.contains(position))
+            {
+                let cwd = app.cwd.as_deref().unwrap_or(Path::new(""));
+                let column = (position.x - text_area.x) as usize;
+                app.pending_edit =
+                    location_at(row, column, cwd).or_else(|| diagnostic_on(row, &app.problems));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Text of every row of `area` as drawn, one character per cell so columns match characters.
+pub fn screen_rows(buffer: &Buffer, area: Rect) -> Vec<String> {
+    (area.top()..area.bottom())
+        .map(|y| {
+            (area.left()..area.right())
+                .map(|x| {
+                    buffer
+                        .cell((x, y))
+                        .and_then(|cell| cell.symbol().chars().next())
+                        .unwrap_or(' ')
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The file location under `column` of `row`, e.g. `src/main.rs:10:5`, `src/app.ts(10,5)` or
+/// just `src/main.rs`, when the file exists in `cwd`.
+fn location_at(row: &str, column: usize, cwd: &Path) -> Option<Location> {
+    let chars = row.chars().collect::<Vec<char>>();
+    let is_part = |c: &char| !c.is_whitespace() && !"\"'`<>|".contains(*c);
+    if !chars.get(column).is_some_and(is_part) {
+        return None;
+    }
+    let start = chars[..column]
+        .iter()
+        .rposition(|c| !is_part(c))
+        .map_or(0, |i| i + 1);
+    let end = chars[column..]
+        .iter()
+        .position(|c| !is_part(c))
+        .map_or(chars.len(), |i| column + i);
+    let token = chars[start..end].iter().collect::<String>();
+    let token = token.trim_start_matches(['(', '[']);
+
+    let number = |n: Option<&str>| n.and_then(|n| n.parse::<u32>().ok());
+    let (file, line, column) = match token.split_once('(') {
+        // tsc and MSVC style
+        Some((file, position)) => {
+            let mut numbers = position
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|n| !n.is_empty());
+            (file, number(numbers.next()), number(numbers.next()))
+        }
+        None => {
+            let token = token.trim_end_matches([')', ']', ',', ';', '.', ':']);
+            let mut parts = token.split(':');
+            let file = parts.next().unwrap_or_default();
+            (file, number(parts.next()), number(parts.next()))
+        }
+    };
+    if file.is_empty() || !cwd.join(file).is_file() {
+        return None;
+    }
+    Some(Location {
+        file: PathBuf::from(file),
+        line: line.unwrap_or(1),
+        column: column.unwrap_or(1),
+    })
+}
+
+/// Location of the diagnostic whose message is on `row`, e.g. `error[E0308]: mismatched types`.
+fn diagnostic_on(row: &str, problems: &Problems) -> Option<Location> {
+    problems
+        .diagnostics
+        .iter()
+        .find(|d| !d.message.is_empty() && row.contains(&d.message))
+        .map(|d| Location {
+            file: d.file.clone(),
+            line: d.line,
+            column: d.column,
+        })
+}
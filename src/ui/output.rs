@@ -1,12 +1,16 @@
 use ratatui::{
     layout::{Alignment, Margin, Rect},
-    style::{Color, Style, Stylize},
-    text::Text,
-    widgets::{Block, Borders, Paragraph},
+    style::{Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Paragraph, Wrap},
     Frame,
 };
 
-use crate::process_manager::ProcessExecution;
+use super::{diagnostics::Severity, durations::RunDurations, problems::Problems, theme::theme};
+use crate::{process_manager::ProcessExecution, time_format::human_duration};
+
+/// Change against the median in percent from which the trend is highlighted
+const TREND_THRESHOLD: f64 = 10.0;
 
 // Animated running string based on time
 // Has to update every 100 miliseconds
@@ -24,32 +28,85 @@ fn running_string() -> String {
     format!(" {} Running {} ", running, running)
 }
 
-pub fn render_output(f: &mut Frame, area: &Rect, execution: &mut Option<ProcessExecution>) {
+/// Elapsed time of the run and, once finished, its difference to the median of the previous
+/// runs of the command, e.g. ` 12.3s, +40% vs median `.
+fn timing_title(execution: &ProcessExecution, durations: &RunDurations) -> Line<'static> {
+    let elapsed = execution.elapsed();
+    let mut spans = vec![Span::raw(format!(" {}", human_duration(elapsed)))];
+    match (execution.end(), durations.median_before(execution)) {
+        (None, Some(median)) => {
+            spans.push(Span::raw(format!(", median {}", human_duration(median))));
+        }
+        (Some(end), Some(median)) if !end.cancelled && !median.is_zero() => {
+            let change = (elapsed.as_secs_f64() / median.as_secs_f64() - 1.0) * 100.0;
+            let color = if change >= TREND_THRESHOLD {
+                theme().failure
+            } else if change <= -TREND_THRESHOLD {
+                theme().success
+            } else {
+                theme().muted
+            };
+            spans.push(Span::raw(", "));
+            spans.push(Span::styled(
+                format!("{:+.0}% vs median", change),
+                Style::default().fg(color),
+            ));
+        }
+        _ => {}
+    }
+    spans.push(Span::raw(" "));
+    Line::from(spans)
+}
+
+/// What was drawn by `render_output`, for scrolling and clicks.
+#[derive(Clone, Copy, Default)]
+pub struct RenderedOutput {
+    /// Index of the first line shown, counting only the non-empty lines like the panel does
+    pub first: usize,
+    pub shown: usize,
+    pub total: usize,
+    /// Where the lines are drawn
+    pub text_area: Rect,
+    /// Where the status of the run is shown in the title
+    pub status_title: Rect,
+}
+
+/// Renders the output of the execution, following its end or from line `scroll` on when it has
+/// been scrolled up.
+pub fn render_output(
+    f: &mut Frame,
+    area: &Rect,
+    execution: &mut Option<ProcessExecution>,
+    wrap: bool,
+    scroll: Option<usize>,
+    problems: &Problems,
+    durations: &RunDurations,
+) -> RenderedOutput {
     let status_color = match execution {
         Some(exe) => {
-            if exe.cancelled {
-                Color::Gray
+            if exe.is_cancelled() {
+                theme().muted
             } else {
-                match exe.child.try_wait() {
+                match exe.try_wait() {
                     Ok(Some(code)) => match code.code() {
-                        Some(0) => Color::Green,
-                        Some(_) => Color::LightRed,
-                        None => Color::LightYellow,
+                        Some(0) => theme().success,
+                        Some(_) => theme().failure,
+                        None => theme().warning,
                     },
-                    Ok(None) => Color::LightYellow,
-                    Err(_) => Color::Red,
+                    Ok(None) => theme().warning,
+                    Err(_) => theme().error,
                 }
             }
         }
-        None => Color::Gray,
+        None => theme().muted,
     };
 
     let header = match execution {
         Some(exe) => {
-            if exe.cancelled {
+            if exe.is_cancelled() {
                 " Cancelled ".to_string()
             } else {
-                match exe.child.try_wait() {
+                match exe.try_wait() {
                     Ok(Some(code)) => match code.code() {
                         Some(0) => " Success ".to_string(),
                         Some(e) => format!(" Error: {} ", e),
@@ -63,44 +120,146 @@ pub fn render_output(f: &mut Frame, area: &Rect, execution: &mut Option<ProcessE
         None => "No command".to_string(),
     };
 
-    let header_block = Block::default()
+    let header_width = Line::raw(header.as_str()).width() as u16;
+    let mut header_block = Block::default()
         .borders(Borders::ALL)
         .border_style(Style::default().fg(status_color))
         .border_type(ratatui::widgets::BorderType::Double)
         .title(header)
         .title_alignment(Alignment::Center)
         // .bg(Color::Rgb(30, 34, 42))
-        .title_style(Style::default().fg(Color::Yellow).bold());
+        .title_style(Style::default().fg(theme().title).bold());
+    // Centered titles are drawn next to each other, separated by a space
+    let mut centered_width = header_width;
+    if let Some(exe) = execution {
+        let timing = timing_title(exe, durations);
+        centered_width += timing.width() as u16 + 1;
+        header_block = header_block.title(timing);
+    }
+    let counts = [
+        (problems.count(Severity::Error), "error", theme().failure),
+        (
+            problems.count(Severity::Warning),
+            "warning",
+            theme().warning,
+        ),
+    ];
+    for (count, label, color) in counts.into_iter().filter(|(count, _, _)| *count > 0) {
+        let plural = if count == 1 { "" } else { "s" };
+        header_block = header_block.title(
+            Line::from(format!(" {} {}{} ", count, label, plural))
+                .right_aligned()
+                .style(Style::default().fg(color).bold()),
+        );
+    }
     f.render_widget(header_block, *area);
 
     let inner_area = area.inner(Margin {
         horizontal: 2,
         vertical: 1,
     });
-    // force clear inner_area
+    let titles_width = area.width.saturating_sub(2);
+    let mut rendered = RenderedOutput {
+        text_area: inner_area,
+        status_title: Rect {
+            x: area.x + 1 + titles_width.saturating_sub(centered_width) / 2,
+            y: area.y,
+            width: header_width.min(titles_width),
+            height: 1,
+        },
+        ..Default::default()
+    };
 
     match execution {
         Some(ref mut exe) => {
             let out = exe.output.lock().unwrap();
-            // Take bytes until 10 occurences of newline
-            let mut last_10: Vec<String> = out
+            let lines = out
                 .split(|&x| x == b'\n')
-                .rev()
                 .filter(|x| !x.is_empty())
-                .take(inner_area.height as usize)
-                .map(|x| String::from_utf8_lossy(x))
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>();
-            last_10.reverse();
+                .map(|x| String::from_utf8_lossy(x));
+            rendered.total = lines.clone().count();
+            let height = inner_area.height as usize;
+            let width = inner_area.width.max(1) as usize;
+            let rows = |line: &str| Text::raw(line).width().max(1).div_ceil(width);
 
-            // eprintln!("Last 10: {:?}", last_10);
+            let paragraph = match scroll {
+                Some(first) => {
+                    // Take lines from the first one on until they fill the area
+                    rendered.first = first.min(rendered.total.saturating_sub(1));
+                    let mut shown = vec![];
+                    let mut used = 0;
+                    for line in lines.skip(rendered.first) {
+                        if used >= height {
+                            break;
+                        }
+                        used += if wrap { rows(&line) } else { 1 };
+                        shown.push(line.to_string());
+                    }
+                    rendered.shown = shown.len();
+                    let paragraph = Paragraph::new(Text::from_iter(shown));
+                    match wrap {
+                        true => paragraph.wrap(Wrap { trim: false }),
+                        false => paragraph,
+                    }
+                }
+                None if wrap => {
+                    // Take lines until they fill the area when wrapped, then scroll to the bottom
+                    let mut used = 0;
+                    let mut last_10 = vec![];
+                    for line in lines.rev() {
+                        if used >= height {
+                            break;
+                        }
+                        used += rows(&line);
+                        last_10.push(line.to_string());
+                    }
+                    last_10.reverse();
+                    let overflow = used.saturating_sub(height) as u16;
+                    rendered.shown = last_10.len();
+                    rendered.first = rendered.total - rendered.shown;
+                    Paragraph::new(Text::from_iter(last_10))
+                        .wrap(Wrap { trim: false })
+                        .scroll((overflow, 0))
+                }
+                None => {
+                    let mut last_10 = lines
+                        .rev()
+                        .take(height)
+                        .map(|x| x.to_string())
+                        .collect::<Vec<String>>();
+                    last_10.reverse();
+                    rendered.shown = last_10.len();
+                    rendered.first = rendered.total - rendered.shown;
+                    Paragraph::new(Text::from_iter(last_10))
+                }
+            };
 
-            let paragraph = Paragraph::new(Text::from_iter(last_10));
             f.render_widget(paragraph, inner_area);
+            if scroll.is_some() {
+                let position = format!(
+                    " lines {}-{} of {} ",
+                    rendered.first + 1,
+                    rendered.first + rendered.shown,
+                    rendered.total
+                );
+                // On the bottom border, like a title
+                let width = (position.len() as u16).min(area.width.saturating_sub(2));
+                f.render_widget(
+                    Span::styled(position, Style::default().fg(theme().muted)),
+                    Rect {
+                        x: area.right().saturating_sub(width + 1),
+                        y: area.bottom().saturating_sub(1),
+                        width,
+                        height: 1,
+                    },
+                );
+            }
+            rendered
         }
         None => {
             let paragraph = Paragraph::new(vec![ratatui::text::Line::from("No command running")]);
             f.render_widget(paragraph, inner_area);
+            rendered
         }
     }
 }
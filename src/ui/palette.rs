@@ -0,0 +1,207 @@
+use ratatui::{
+    layout::{Alignment, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use super::{keys::Action, theme::theme};
+
+const MAX_VISIBLE: u16 = 10;
+
+/// State of the `:` command palette. Typing filters the actions with fuzzy matching,
+/// actions that need an argument switch the palette to a prompt for it.
+pub struct Palette {
+    pub input: String,
+    selected: usize,
+    pub prompt: Option<Action>,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Palette {
+            input: String::new(),
+            selected: 0,
+            prompt: None,
+        }
+    }
+
+    /// Opens the palette as a prompt for the argument of `action`.
+    pub fn prompt(action: Action, initial: String) -> Self {
+        Palette {
+            input: initial,
+            selected: 0,
+            prompt: Some(action),
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.selected = 0;
+    }
+
+    pub fn select_next(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + 1) % count;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        let count = self.matches().len();
+        if count > 0 {
+            self.selected = (self.selected + count - 1) % count;
+        }
+    }
+
+    /// Completes the input to the name of the selected action.
+    pub fn complete(&mut self) {
+        if let Some(action) = self.selected() {
+            self.input = action.name().to_string();
+        }
+    }
+
+    pub fn selected(&self) -> Option<Action> {
+        self.matches().get(self.selected).copied()
+    }
+
+    /// Actions matching the input, none while prompting for an argument, so selecting and
+    /// completing leave the typed argument alone.
+    pub fn matches(&self) -> Vec<Action> {
+        if self.prompt.is_some() {
+            return vec![];
+        }
+        let mut scored = Action::ALL
+            .into_iter()
+            .filter(|a| *a != Action::Palette)
+            .filter_map(|a| fuzzy_score(&self.input, a.name()).map(|score| (score, a)))
+            .collect::<Vec<(i32, Action)>>();
+        // Stable sort keeps the declaration order for equal scores
+        scored.sort_by_key(|(score, _)| -score);
+        scored.into_iter().map(|(_, a)| a).collect()
+    }
+}
+
+/// Scores how well `query` matches `candidate` as a case insensitive subsequence,
+/// favouring consecutive characters and matches at the start of words.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate = candidate.chars().collect::<Vec<char>>();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_ascii_lowercase();
+        let found =
+            (position..candidate.len()).find(|&i| candidate[i].to_ascii_lowercase() == q)?;
+        score += 1;
+        if previous_match.is_some_and(|p| p + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous_match = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+pub fn render_palette(f: &mut Frame, area: Rect, palette: &Palette) {
+    let matches = palette.matches();
+    let list_height = (matches.len() as u16).min(MAX_VISIBLE);
+    let width = area.width.saturating_sub(4).min(60);
+    let popup = Rect {
+        x: area.x + (area.width.saturating_sub(width)) / 2,
+        y: area.y + area.height / 5,
+        width,
+        height: (list_height + 3).min(area.height),
+    };
+    f.render_widget(Clear, popup);
+
+    let title = match palette.prompt {
+        Some(action) => format!(" {} ", action.description()),
+        None => " Command Palette ".to_string(),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme().info))
+        .border_type(ratatui::widgets::BorderType::Rounded)
+        .title(title)
+        .title_alignment(Alignment::Center)
+        .title_style(Style::default().fg(theme().title).bold());
+    let inner = block.inner(popup);
+    f.render_widget(block, popup);
+
+    let input = Paragraph::new(Line::from(vec![
+        Span::styled(":", Style::default().fg(theme().info).bold()),
+        Span::raw(palette.input.clone()),
+        Span::styled("█", Style::default().fg(theme().muted)),
+    ]));
+    f.render_widget(
+        input,
+        Rect {
+            height: 1.min(inner.height),
+            ..inner
+        },
+    );
+
+    if matches.is_empty() || inner.height < 2 {
+        return;
+    }
+    let items = matches
+        .iter()
+        .map(|a| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:<16}", a.name()),
+                    Style::default().fg(theme().accent),
+                ),
+                Span::styled(a.description(), Style::default().fg(theme().muted)),
+            ]))
+        })
+        .collect::<Vec<ListItem>>();
+    let list = List::new(items).highlight_style(theme().highlight_style().bold());
+    let mut state = ListState::default().with_selected(Some(palette.selected));
+    f.render_stateful_widget(
+        list,
+        Rect {
+            y: inner.y + 1,
+            height: inner.height - 1,
+            ..inner
+        },
+        &mut state,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_fuzzy_matches() {
+        let mut palette = Palette::new();
+        for c in "tgarr".chars() {
+            palette.push(c);
+        }
+        assert_eq!(palette.selected(), Some(Action::ToggleArrangement));
+        palette.complete();
+        assert_eq!(palette.input, "toggle-arrangement");
+    }
+
+    #[test]
+    fn leaves_prompt_input_alone() {
+        let mut palette = Palette::prompt(Action::YankMatches, "err".to_string());
+        palette.complete();
+        palette.select_next();
+        palette.select_previous();
+        palette.complete();
+        assert_eq!(palette.input, "err");
+        assert_eq!(palette.selected(), None);
+    }
+}
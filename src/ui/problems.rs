@@ -0,0 +1,208 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    layout::{Alignment, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, List, ListItem, ListState},
+    Frame,
+};
+
+use super::{
+    diagnostics::{Diagnostic, DiagnosticParser, Severity},
+    theme::theme,
+};
+use crate::{
+    ansi::plain_lines,
+    process_manager::ProcessExecution,
+    test_results::{self, Location, TestSummary},
+};
+
+const MAX_VISIBLE: u16 = 8;
+/// How often the test results are parsed again while the output grows
+const TESTS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Diagnostics and test results found in the output of the current run. The panel lists the
+/// diagnostics followed by the failing tests.
+#[derive(Default)]
+pub struct Problems {
+    run_id: u64,
+    /// Bytes of the output turned into `lines`
+    parsed_len: usize,
+    /// Output lines without escape codes, complete ones only until the run has ended
+    lines: Vec<String>,
+    /// Whether the output was parsed after the run ended
+    finished: bool,
+    parser: DiagnosticParser,
+    tests_parsed_at: Option<Instant>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub tests: Option<TestSummary>,
+    selected: usize,
+}
+
+impl Problems {
+    /// Parses the lines added to the output since the last time.
+    pub fn update(&mut self, execution: &ProcessExecution) {
+        let ended = execution.end().is_some();
+        let output = execution.output.lock().unwrap();
+        // A new run, or the output was cleared
+        if execution.run_id != self.run_id || output.len() < self.parsed_len {
+            *self = Problems {
+                run_id: execution.run_id,
+                ..Problems::default()
+            };
+        }
+        // The last line may still be written to while the run goes on
+        let end = match ended {
+            true => output.len(),
+            false => output[self.parsed_len..]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(self.parsed_len, |i| self.parsed_len + i + 1),
+        };
+        if end == self.parsed_len && (self.finished || !ended) {
+            return;
+        }
+        let mut lines = plain_lines(&output[self.parsed_len..end]);
+        drop(output);
+        // The line after the last newline
+        if lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        self.lines.append(&mut lines);
+        self.parsed_len = end;
+        self.finished = ended;
+        let found = self.parser.parse(&self.lines, ended);
+        self.diagnostics.extend(found);
+        // Test results are spread over the output, so they are parsed from the start every time
+        if ended
+            || self
+                .tests_parsed_at
+                .is_none_or(|at| at.elapsed() >= TESTS_INTERVAL)
+        {
+            self.tests = test_results::parse(&self.lines);
+            self.tests_parsed_at = Some(Instant::now());
+        }
+        self.selected = self.selected.min(self.len().saturating_sub(1));
+    }
+
+    fn failing_tests(&self) -> &[test_results::FailedTest] {
+        self.tests.as_ref().map_or(&[], |t| t.failing.as_slice())
+    }
+
+    fn len(&self) -> usize {
+        self.diagnostics.len() + self.failing_tests().len()
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    pub fn select_next(&mut self) {
+        let count = self.len();
+        if count > 0 {
+            self.selected = (self.selected + 1) % count;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        let count = self.len();
+        if count > 0 {
+            self.selected = (self.selected + count - 1) % count;
+        }
+    }
+
+    /// Selects the problem shown on `row` of a panel showing `rows` problems, returns whether
+    /// there is one on that row.
+    pub fn select_row(&mut self, row: usize, rows: usize) -> bool {
+        // The list scrolls just far enough to show the selected problem on the last row
+        let offset = (self.selected + 1).saturating_sub(rows);
+        let index = offset + row;
+        if index < self.len() {
+            self.selected = index;
+        }
+        index < self.len()
+    }
+
+    /// Location of the selected diagnostic or failing test, when it is known.
+    pub fn selected_location(&self) -> Option<Location> {
+        match self.diagnostics.get(self.selected) {
+            Some(d) => Some(Location {
+                file: d.file.clone(),
+                line: d.line,
+                column: d.column,
+            }),
+            None => self
+                .failing_tests()
+                .get(self.selected - self.diagnostics.len())
+                .and_then(|t| t.location.clone()),
+        }
+    }
+
+    /// Height of the panel, which is hidden when there are no problems.
+    pub fn height(&self) -> u16 {
+        match self.len() {
+            0 => 0,
+            n => (n as u16).min(MAX_VISIBLE) + 2,
+        }
+    }
+}
+
+pub fn render_problems(f: &mut Frame, area: Rect, problems: &Problems, focused: bool) {
+    let items = problems
+        .diagnostics
+        .iter()
+        .map(|d| {
+            let (label, color) = match d.severity {
+                Severity::Error => ("error", theme().failure),
+                Severity::Warning => ("warning", theme().warning),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<8}", label), Style::default().fg(color).bold()),
+                Span::styled(
+                    format!("{}:{}:{}", d.file.display(), d.line, d.column),
+                    Style::default().fg(theme().accent),
+                ),
+                Span::raw(" "),
+                Span::raw(d.message.clone()),
+            ]))
+        })
+        .chain(problems.failing_tests().iter().map(|t| {
+            let mut spans = vec![
+                Span::styled(
+                    format!("{:<8}", "failed"),
+                    Style::default().fg(theme().failure).bold(),
+                ),
+                Span::raw(t.name.clone()),
+            ];
+            if let Some(l) = &t.location {
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(
+                    format!("{}:{}:{}", l.file.display(), l.line, l.column),
+                    Style::default().fg(theme().accent),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        }))
+        .collect::<Vec<ListItem>>();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme().info))
+                .border_type(match focused {
+                    true => BorderType::Thick,
+                    false => BorderType::Rounded,
+                })
+                .title(" Problems (Enter to open) ")
+                .title_alignment(Alignment::Center)
+                .title_style(Style::default().fg(theme().title).bold()),
+        )
+        .highlight_style(theme().highlight_style());
+    let mut state = ListState::default().with_selected(Some(problems.selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs, thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+use crate::{config::Config, process_manager::ProcessExecution};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+/// sysinfo doesn't update the CPU usage more often than this
+const MIN_INTERVAL: Duration = Duration::from_millis(200);
+/// Clock ticks per second of the times in `/proc/<pid>/stat`, fixed for userspace on Linux
+const USER_HZ: f32 = 100.0;
+/// Samples kept for the graphs, more than fit on the widest terminals
+const HISTORY_LEN: usize = 500;
+
+/// Resources used by the process tree of the command at one point in time.
+#[derive(Clone, Copy, Default)]
+pub struct TreeUsage {
+    /// Summed over the processes, so it can exceed 100% on several cores
+    pub cpu: f32,
+    pub rss: u64,
+    pub threads: usize,
+    pub children: usize,
+}
+
+/// Usage of the process tree of a run, sampled while it runs. The peaks, CPU time and the
+/// latest samples are kept once the run has finished.
+#[derive(Clone, Default)]
+pub struct RunUsage {
+    /// `None` before the first sample and once the run has finished
+    pub current: Option<TreeUsage>,
+    pub peak_rss: u64,
+    /// CPU usage in percent
+    pub peak_cpu: u64,
+    /// CPU time summed over the samples, which misses processes living shorter than a sample
+    pub cpu_time: Duration,
+    pub finished: bool,
+    last_sample: Option<Instant>,
+    /// CPU usage in percent of the latest samples, up to `HISTORY_LEN`
+    pub cpu_history: VecDeque<u64>,
+    /// RSS of the latest samples
+    pub rss_history: VecDeque<u64>,
+    /// Peak CPU usage and RSS of the previous run, so the graphs of consecutive runs share a scale
+    pub previous_peaks: Option<(u64, u64)>,
+}
+
+impl RunUsage {
+    /// Starts over for a new run, keeping the peaks of the current one.
+    fn start(&mut self) {
+        let previous_peaks = match self.last_sample {
+            Some(_) => Some((self.peak_cpu, self.peak_rss)),
+            None => self.previous_peaks,
+        };
+        *self = RunUsage {
+            previous_peaks,
+            ..Default::default()
+        };
+    }
+
+    fn add(&mut self, usage: TreeUsage) {
+        let now = Instant::now();
+        if let Some(last) = self.last_sample {
+            // The CPU usage is the average since the previous refresh
+            self.cpu_time += (now - last).mul_f64(usage.cpu as f64 / 100.0);
+        }
+        self.last_sample = Some(now);
+        let cpu = usage.cpu.round() as u64;
+        self.peak_rss = self.peak_rss.max(usage.rss);
+        self.peak_cpu = self.peak_cpu.max(cpu);
+        if self.cpu_history.len() == HISTORY_LEN {
+            self.cpu_history.pop_front();
+            self.rss_history.pop_front();
+        }
+        self.cpu_history.push_back(cpu);
+        self.rss_history.push_back(usage.rss);
+        self.current = Some(usage);
+    }
+
+    fn finish(&mut self) {
+        self.current = None;
+        self.finished = true;
+    }
+}
+
+/// Usage of the whole system and of the current run, sent to the UI after every sample.
+#[derive(Clone, Default)]
+pub struct Sample {
+    pub cpu: f32,
+    pub used_memory: u64,
+    pub total_memory: u64,
+    pub used_swap: u64,
+    pub total_swap: u64,
+    pub run: RunUsage,
+}
+
+/// Handle to the sampler thread, which stops once the handle is dropped.
+pub struct Sampler {
+    execution_tx: Sender<ProcessExecution>,
+    pub rx: Receiver<Sample>,
+}
+
+impl Sampler {
+    /// Starts sampling on a background thread, every `interval` from the `[stats]` section.
+    pub fn spawn(config: &Config) -> Result<Sampler, String> {
+        let interval = match config.get("stats", "interval") {
+            Some(value) => parse_interval(value)
+                .filter(|interval| *interval >= MIN_INTERVAL)
+                .ok_or_else(|| {
+                    format!(
+                        "Expected an interval of at least 200ms for interval in [stats], got {:?}",
+                        value
+                    )
+                })?,
+            None => DEFAULT_INTERVAL,
+        };
+        let (execution_tx, execution_rx) = unbounded();
+        let (tx, rx) = unbounded();
+        thread::spawn(move || run(interval, execution_rx, tx));
+        Ok(Sampler { execution_tx, rx })
+    }
+
+    /// Samples the process tree of `execution` from now on.
+    pub fn follow(&self, execution: ProcessExecution) {
+        let _ = self.execution_tx.send(execution);
+    }
+}
+
+fn run(interval: Duration, execution_rx: Receiver<ProcessExecution>, tx: Sender<Sample>) {
+    let mut system = System::new();
+    let mut sample = Sample::default();
+    let mut execution: Option<ProcessExecution> = None;
+    let mut ticks = CpuTicks::default();
+    let mut next = Instant::now();
+    loop {
+        match execution_rx.recv_timeout(next.saturating_duration_since(Instant::now())) {
+            Ok(exe) => {
+                sample.run.start();
+                ticks = CpuTicks::default();
+                execution = Some(exe);
+                // Don't show the usage of the previous run until the next sample
+                if tx.send(sample.clone()).is_err() {
+                    return;
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        next = Instant::now() + interval;
+
+        system.refresh_memory();
+        system.refresh_cpu_usage();
+        sample.cpu = system.global_cpu_usage();
+        sample.used_memory = system.used_memory();
+        sample.total_memory = system.total_memory();
+        sample.used_swap = system.used_swap();
+        sample.total_swap = system.total_swap();
+        match &execution {
+            Some(exe) if exe.end().is_none() => {
+                if let Some(pid) = exe.pid() {
+                    let usage = sample_tree(&mut system, &mut ticks, Pid::from_u32(pid));
+                    sample.run.add(usage);
+                }
+            }
+            Some(_) if !sample.run.finished => sample.run.finish(),
+            _ => {}
+        }
+        if tx.send(sample.clone()).is_err() {
+            return;
+        }
+    }
+}
+
+/// CPU time of the processes of the tree at the previous sample, as sysinfo only computes the CPU
+/// usage when refreshing every process.
+#[derive(Default)]
+struct CpuTicks {
+    per_process: HashMap<Pid, u64>,
+    sampled: Option<Instant>,
+}
+
+impl CpuTicks {
+    /// CPU usage of `pids` since the previous sample. Processes started since then count all
+    /// their CPU time.
+    fn usage(&mut self, pids: &[Pid]) -> f32 {
+        let now = Instant::now();
+        let per_process = pids
+            .iter()
+            .filter_map(|pid| Some((*pid, process_ticks(*pid)?)))
+            .collect::<HashMap<_, _>>();
+        let used = per_process
+            .iter()
+            .map(|(pid, ticks)| ticks - self.per_process.get(pid).map_or(0, |t| *t.min(ticks)))
+            .sum::<u64>();
+        let elapsed = self.sampled.map(|sampled| now - sampled);
+        self.per_process = per_process;
+        self.sampled = Some(now);
+        match elapsed {
+            Some(elapsed) if !elapsed.is_zero() => {
+                used as f32 / USER_HZ / elapsed.as_secs_f32() * 100.0
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// User and system time of a process in clock ticks, from `/proc/<pid>/stat`.
+fn process_ticks(pid: Pid) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The name in parentheses may contain spaces, the fields after it are state, ppid, ...
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let utime = fields.next()?.parse::<u64>().ok()?;
+    let stime = fields.next()?.parse::<u64>().ok()?;
+    Some(utime + stime)
+}
+
+/// Refreshes the processes of the tree rooted at `root` and sums their usage. The tree is read
+/// from the `children` files of Linux, other systems refresh every process to find it.
+fn sample_tree(system: &mut System, ticks: &mut CpuTicks, root: Pid) -> TreeUsage {
+    let (pids, cpu) = match linux_tree(root) {
+        Some(pids) => {
+            let refresh_kind = ProcessRefreshKind::new().with_memory();
+            system.refresh_processes_specifics(ProcessesToUpdate::Some(&pids), true, refresh_kind);
+            let cpu = ticks.usage(&pids);
+            (pids, Some(cpu))
+        }
+        None => {
+            let refresh_kind = ProcessRefreshKind::new().with_cpu().with_memory();
+            system.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh_kind);
+            let mut pids = vec![root];
+            pids.extend(get_all_children(system, root));
+            (pids, None)
+        }
+    };
+    let mut usage = TreeUsage {
+        children: pids.len().saturating_sub(1),
+        ..Default::default()
+    };
+    for process in pids.iter().filter_map(|pid| system.process(*pid)) {
+        usage.cpu += process.cpu_usage();
+        usage.rss += process.memory();
+        // The tasks include the main thread, and are only known on Linux
+        usage.threads += process.tasks().map_or(1, |tasks| tasks.len().max(1));
+    }
+    if let Some(cpu) = cpu {
+        usage.cpu = cpu;
+    }
+    usage
+}
+
+/// `root` and its descendants from `/proc/<pid>/task/<tid>/children`, `None` when the kernel
+/// doesn't provide them.
+fn linux_tree(root: Pid) -> Option<Vec<Pid>> {
+    let mut pids = vec![root];
+    let mut i = 0;
+    while let Some(pid) = pids.get(i).copied() {
+        i += 1;
+        let tasks = match fs::read_dir(format!("/proc/{}/task", pid)) {
+            Ok(tasks) => tasks,
+            Err(_) if pid == root => return None,
+            // Gone since it was listed as a child
+            Err(_) => continue,
+        };
+        for task in tasks.flatten() {
+            let children = match fs::read_to_string(task.path().join("children")) {
+                Ok(children) => children,
+                Err(_) if pid == root => return None,
+                Err(_) => continue,
+            };
+            pids.extend(
+                children
+                    .split_whitespace()
+                    .filter_map(|c| c.parse::<Pid>().ok()),
+            );
+        }
+    }
+    Some(pids)
+}
+
+fn get_all_children(system: &System, pid: Pid) -> HashSet<Pid> {
+    let mut children = HashSet::new();
+
+    for (proc_pid, process) in system.processes() {
+        // Threads are listed as processes on Linux, with the process as their parent
+        if process.parent() == Some(pid) && process.thread_kind().is_none() {
+            // Add the child PID
+            children.insert(*proc_pid);
+            // Recursively find its children
+            children.extend(get_all_children(system, *proc_pid));
+        }
+    }
+
+    children
+}
+
+/// `500ms`, `2s` or a number of milliseconds.
+fn parse_interval(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Some(ms) = value.strip_suffix("ms") {
+        ms.trim().parse().ok().map(Duration::from_millis)
+    } else if let Some(s) = value.strip_suffix('s') {
+        // `from_secs_f64` panics on negative, infinite and NaN values, like `-1s` or `infs`
+        let s = s.trim().parse().ok()?;
+        Duration::try_from_secs_f64(s).ok()
+    } else {
+        value.parse().ok().map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_interval(" 2 s "), Some(Duration::from_secs(2)));
+        assert_eq!(parse_interval("0.25s"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_interval("750"), Some(Duration::from_millis(750)));
+    }
+
+    #[test]
+    fn keeps_the_latest_samples_and_the_peaks() {
+        let mut usage = RunUsage::default();
+        for i in 0..HISTORY_LEN as u64 + 10 {
+            let cpu = if i == 0 { 300.0 } else { 50.0 };
+            usage.add(TreeUsage {
+                cpu,
+                rss: i,
+                ..Default::default()
+            });
+        }
+        assert_eq!(usage.cpu_history.len(), HISTORY_LEN);
+        assert_eq!(usage.rss_history.front(), Some(&10));
+        assert_eq!(
+            (usage.peak_cpu, usage.peak_rss),
+            (300, HISTORY_LEN as u64 + 9)
+        );
+        usage.finish();
+        usage.start();
+        assert_eq!(usage.previous_peaks, Some((300, HISTORY_LEN as u64 + 9)));
+        assert!(usage.cpu_history.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_intervals() {
+        for value in ["", "-1s", "NaNs", "infs", "1e30s", "-5ms", "fast"] {
+            assert_eq!(parse_interval(value), None, "{:?}", value);
+        }
+    }
+}
@@ -1,39 +1,66 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 
 use ratatui::{
     layout::{Alignment, Constraint, Layout, Margin, Rect},
-    style::{Color, Style, Stylize},
+    style::{Style, Stylize},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, LineGauge},
+    widgets::{Block, Borders, LineGauge, Sparkline},
     Frame,
 };
 use sysinfo::{Gid, Pid, System};
 
-use crate::process_manager::{ProcessExecution, Trigger};
+use super::{
+    sampler::{RunUsage, Sample},
+    theme::theme,
+};
+use crate::{
+    await_modify::WatchStatus,
+    hooks::{HookOutcome, HookResults},
+    process_manager::ProcessExecution,
+    test_results::TestSummary,
+    time_format::human_duration,
+};
+
+const MIN_WIDTH_FOR_GRAPHS: u16 = 90;
 
 pub fn render_stats(
     f: &mut Frame,
     area: &Rect,
-    system: &mut System,
+    sample: &Sample,
     execution: &mut Option<ProcessExecution>,
-    _current_pid: &Pid,
+    watch: &WatchStatus,
+    hooks: Option<HookResults>,
+    tests: Option<&TestSummary>,
 ) {
     let trigger_str = match execution {
-        Some(exe) => match &exe.trigger {
-            Trigger::Manual => "Manual".to_string(),
-            Trigger::Start => "Start".to_string(),
-            Trigger::Modify(p) => format!("Modify: {}", p),
-        },
+        Some(exe) => format!("#{} {}", exe.run_id, exe.trigger),
         None => "None".to_string(),
     };
-    let header_block = Block::default()
+    let mut header_block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::LightGreen))
+        .border_style(Style::default().fg(theme().border))
         .border_type(ratatui::widgets::BorderType::Rounded)
         .title(trigger_str)
         .title_alignment(Alignment::Center)
         // .bg(Color::Rgb(30, 34, 42))
-        .title_style(Style::default().fg(Color::Yellow).bold());
+        .title_style(Style::default().fg(theme().title).bold());
+    if let Some(skipped) = watch.skipped {
+        header_block = header_block.title_bottom(
+            Line::from(format!(" {} unchanged saves skipped ", skipped))
+                .right_aligned()
+                .style(Style::default().fg(theme().muted)),
+        );
+    }
+    if let Some(files) = watch.files {
+        header_block = header_block.title_bottom(
+            Line::from(format!(" watching {} files read by the last run ", files))
+                .right_aligned()
+                .style(Style::default().fg(theme().muted)),
+        );
+    }
+    if let Some(hooks) = hooks.filter(|h| !h.results.is_empty()) {
+        header_block = header_block.title_bottom(render_hooks(&hooks).left_aligned());
+    }
     f.render_widget(header_block, *area);
 
     let inner_area = area.inner(Margin {
@@ -41,23 +68,28 @@ pub fn render_stats(
         vertical: 1,
     });
 
-    let (total_cpu_area, total_memory_area, total_swap_area) = split_row_layout(inner_area);
+    // The graphs take a third of wide panels
+    let [inner_area, graphs_area] = match inner_area.width >= MIN_WIDTH_FOR_GRAPHS {
+        true => Layout::horizontal([Constraint::Min(0), Constraint::Ratio(1, 3)])
+            .spacing(2)
+            .areas(inner_area),
+        false => [inner_area, Rect::default()],
+    };
+    render_usage_history(f, graphs_area, &sample.run);
+    let (command_area, total_cpu_area, total_memory_area, total_swap_area, tests_area) =
+        split_row_layout(inner_area);
+    render_run_usage(f, command_area, &sample.run);
+    if let Some(tests) = tests {
+        render_test_summary(f, tests_area, tests);
+    }
 
     match execution {
         Some(_) => {
-            let current_time_in_seconds = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis();
-            // If time modlus 1000 is less than 100, then update
-            if current_time_in_seconds % 1000 < 40 {
-                system.refresh_all();
-            }
-            let total_cpu_usage = system.global_cpu_usage();
-            let total_memory = human_friendly_mem(system.total_memory());
-            let total_used_memory = human_friendly_mem(system.used_memory());
-            let total_swap = human_friendly_mem(system.total_swap());
-            let total_used_swap = human_friendly_mem(system.used_swap());
+            let total_cpu_usage = sample.cpu;
+            let total_memory = human_friendly_mem(sample.total_memory);
+            let total_used_memory = human_friendly_mem(sample.used_memory);
+            let total_swap = human_friendly_mem(sample.total_swap);
+            let total_used_swap = human_friendly_mem(sample.used_swap);
             // let exit = match e.child.try_wait() {
             //     Ok(x) => x.map(|e| e.code()),
             //     Err(_) => None,
@@ -68,51 +100,51 @@ pub fn render_stats(
             let (cpu_txt_area, cpu_gauge_area) =
                 split_line_layout(total_cpu_area, (cpu_str).len() as u16);
             f.render_widget(
-                Text::from(cpu_str).style(Style::default().fg(Color::Yellow)),
+                Text::from(cpu_str).style(Style::default().fg(theme().title)),
                 cpu_txt_area,
             );
             f.render_widget(
                 LineGauge::default()
-                    .filled_style(Style::default().fg(Color::Cyan))
-                    .ratio(total_cpu_usage as f64 / 100.0),
+                    .filled_style(theme().gauge_style(theme().gauge))
+                    .ratio((total_cpu_usage as f64 / 100.0).clamp(0.0, 1.0)),
                 cpu_gauge_area,
             );
 
             // Memory
-            let mem_label = Span::from("Memory: ").style(Style::default().fg(Color::Yellow));
+            let mem_label = Span::from("Memory: ").style(Style::default().fg(theme().title));
             let mem_str = Span::from(format!("{} / {} - ", total_used_memory, total_memory));
             let total_memory_str = Text::from(Line::from(vec![mem_label, mem_str]));
             let (mem_txt_area, mem_guage_area) =
                 split_line_layout(total_memory_area, total_memory_str.width() as u16);
             f.render_widget(total_memory_str, mem_txt_area);
-            let mem_color = if system.used_memory() as f64 / system.total_memory() as f64 > 0.8 {
-                Color::Red
+            let mem_color = if usage_ratio(sample.used_memory, sample.total_memory) > 0.8 {
+                theme().error
             } else {
-                Color::Cyan
+                theme().gauge
             };
             f.render_widget(
                 LineGauge::default()
-                    .filled_style(Style::default().fg(mem_color))
-                    .ratio(system.used_memory() as f64 / system.total_memory() as f64),
+                    .filled_style(theme().gauge_style(mem_color))
+                    .ratio(usage_ratio(sample.used_memory, sample.total_memory)),
                 mem_guage_area,
             );
 
             // Swap
-            let swap_label = Span::from("Swap: ").style(Style::default().fg(Color::Yellow));
+            let swap_label = Span::from("Swap: ").style(Style::default().fg(theme().title));
             let swap_str = Span::from(format!("{} / {} - ", total_used_swap, total_swap));
             let total_swap_str = Text::from(Line::from(vec![swap_label, swap_str]));
             let (swap_txt_area, swap_gauge_area) =
                 split_line_layout(total_swap_area, total_swap_str.width() as u16);
             f.render_widget(total_swap_str, swap_txt_area);
-            let swap_color = if system.used_swap() as f64 / system.total_swap() as f64 > 0.8 {
-                Color::Red
+            let swap_color = if usage_ratio(sample.used_swap, sample.total_swap) > 0.8 {
+                theme().error
             } else {
-                Color::Cyan
+                theme().gauge
             };
             f.render_widget(
                 LineGauge::default()
-                    .filled_style(Style::default().fg(swap_color))
-                    .ratio(system.used_swap() as f64 / system.total_swap() as f64),
+                    .filled_style(theme().gauge_style(swap_color))
+                    .ratio(usage_ratio(sample.used_swap, sample.total_swap)),
                 swap_gauge_area,
             );
 
@@ -125,12 +157,179 @@ pub fn render_stats(
     }
 }
 
-fn split_row_layout(area: Rect) -> (Rect, Rect, Rect) {
-    let chunks = Layout::default()
-        .direction(ratatui::layout::Direction::Vertical)
-        .constraints([Constraint::Max(1), Constraint::Max(1), Constraint::Max(1)].as_ref())
-        .split(area);
-    (chunks[0], chunks[1], chunks[2])
+/// Compact line with the outcome of every hook of the current run, e.g. `on_start ✓ 12ms`.
+fn render_hooks(hooks: &HookResults) -> Line<'static> {
+    let mut spans = vec![Span::raw(" ")];
+    for (i, result) in hooks.results.iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::styled(" · ", Style::default().fg(theme().muted)));
+        }
+        spans.push(Span::styled(
+            result.name,
+            Style::default().fg(theme().title),
+        ));
+        let (text, color) = match &result.outcome {
+            HookOutcome::Running => ("…".to_string(), theme().warning),
+            HookOutcome::Finished {
+                code: Some(0),
+                duration,
+                ..
+            } => (format!("✓ {}", human_duration(*duration)), theme().success),
+            HookOutcome::Finished {
+                code, last_line, ..
+            } => {
+                let code = code.map_or_else(|| "signal".to_string(), |c| c.to_string());
+                match last_line.is_empty() {
+                    true => (format!("✗ {}", code), theme().failure),
+                    false => (format!("✗ {}: {}", code, last_line), theme().failure),
+                }
+            }
+            HookOutcome::Failed(e) => (format!("✗ {}", e), theme().failure),
+        };
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(text, Style::default().fg(color)));
+    }
+    spans.push(Span::raw(" "));
+    Line::from(spans)
+}
+
+/// Usage of the command's process tree while it runs, its peak RSS and CPU time once finished.
+fn render_run_usage(f: &mut Frame, area: Rect, usage: &RunUsage) {
+    let label = Span::from("Command: ").style(Style::default().fg(theme().title));
+    let text = match (&usage.current, usage.finished) {
+        (Some(current), _) => format!(
+            "CPU {:.1}% · RSS {} · {} threads · {} children",
+            current.cpu,
+            human_friendly_mem(current.rss),
+            current.threads,
+            current.children
+        ),
+        (None, true) if usage.peak_rss > 0 => format!(
+            "peak RSS {} · CPU time {}",
+            human_friendly_mem(usage.peak_rss),
+            human_duration(usage.cpu_time)
+        ),
+        (None, true) => "finished before it was sampled".to_string(),
+        (None, false) => "starting".to_string(),
+    };
+    f.render_widget(Line::from(vec![label, Span::raw(text)]), area);
+}
+
+/// Sparklines of the CPU usage and RSS of the command over the run, the latest samples when
+/// they don't fit. They stop changing once the run has finished.
+fn render_usage_history(f: &mut Frame, area: Rect, usage: &RunUsage) {
+    if area.is_empty() {
+        return;
+    }
+    let (previous_cpu, previous_rss) = usage.previous_peaks.unwrap_or_default();
+    let graphs = [
+        (
+            "CPU",
+            &usage.cpu_history,
+            shown_sample(&usage.cpu_history, usage.peak_cpu, usage.finished)
+                .map(|cpu| format!("{}%", cpu)),
+            // At least one full core, so idle runs don't look busy
+            previous_cpu.max(100),
+            theme().gauge,
+        ),
+        (
+            "RSS",
+            &usage.rss_history,
+            shown_sample(&usage.rss_history, usage.peak_rss, usage.finished)
+                .map(human_friendly_mem),
+            previous_rss,
+            theme().secondary,
+        ),
+    ];
+    let rows = Layout::vertical([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).split(area);
+    for ((label, history, value, previous_peak, color), row) in graphs.into_iter().zip(rows.iter())
+    {
+        let [label_area, graph_area] =
+            Layout::horizontal([Constraint::Length(14), Constraint::Min(0)]).areas(*row);
+        let mut lines = vec![Line::from(label).style(Style::default().fg(theme().title))];
+        if let Some(value) = value {
+            let prefix = if usage.finished { "peak " } else { "" };
+            lines.push(Line::from(format!("{}{}", prefix, value)));
+        }
+        f.render_widget(Text::from(lines), label_area);
+        let visible = history
+            .iter()
+            .skip(history.len().saturating_sub(graph_area.width as usize))
+            .copied()
+            .collect::<Vec<u64>>();
+        let max = history
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(previous_peak);
+        f.render_widget(
+            Sparkline::default()
+                .data(&visible)
+                .max(max.max(1))
+                .style(Style::default().fg(color)),
+            graph_area,
+        );
+    }
+}
+
+/// The latest sample while running, the peak once finished.
+fn shown_sample(history: &VecDeque<u64>, peak: u64, finished: bool) -> Option<u64> {
+    match finished {
+        true => (!history.is_empty()).then_some(peak),
+        false => history.back().copied(),
+    }
+}
+
+/// One row per line of the panel, rows below the area are empty. A layout of `Max(1)` rows
+/// would give up a row in the middle rather than the last when the area is too small.
+fn split_row_layout(area: Rect) -> (Rect, Rect, Rect, Rect, Rect) {
+    let row = |i: u16| Rect {
+        y: area.y + i.min(area.height),
+        height: u16::from(i < area.height),
+        ..area
+    };
+    (row(0), row(1), row(2), row(3), row(4))
+}
+
+/// Counts of the tests followed by a bar split into passed, failed and ignored.
+fn render_test_summary(f: &mut Frame, area: Rect, tests: &TestSummary) {
+    let counts = [
+        (tests.passed, "passed", theme().success),
+        (tests.failed, "failed", theme().failure),
+        (tests.ignored, "ignored", theme().warning),
+    ];
+    let mut spans = vec![Span::from("Tests: ").style(Style::default().fg(theme().title))];
+    for (i, (count, label, color)) in counts.iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(", "));
+        }
+        spans.push(Span::styled(
+            format!("{} {}", count, label),
+            Style::default().fg(*color),
+        ));
+    }
+    spans.push(Span::raw(" "));
+    let text = Line::from(spans);
+    let (text_area, bar_area) = split_line_layout(area, text.width() as u16);
+    f.render_widget(text, text_area);
+
+    let total = tests.passed + tests.failed + tests.ignored;
+    let width = bar_area.width as usize;
+    let mut bar = vec![];
+    let mut used = 0;
+    for (i, (count, _, color)) in counts.iter().enumerate() {
+        let cells = match (total, i) {
+            (0, _) => 0,
+            // The last part takes the rounding remainder
+            (_, 2) => width - used,
+            _ => (count * width).div_ceil(total).min(width - used),
+        };
+        let cells = if *count == 0 { 0 } else { cells };
+        used += cells;
+        bar.push(Span::styled("━".repeat(cells), Style::default().fg(*color)));
+    }
+    f.render_widget(Line::from(bar), bar_area);
 }
 
 fn split_line_layout<T: Into<u16>>(area: Rect, len: T) -> (Rect, Rect) {
@@ -141,6 +340,15 @@ fn split_line_layout<T: Into<u16>>(area: Rect, len: T) -> (Rect, Rect) {
     (chunks[0], chunks[1])
 }
 
+/// Ratio of used to total, which is 0 when there is nothing in total, e.g. no swap.
+fn usage_ratio(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (used as f64 / total as f64).clamp(0.0, 1.0)
+    }
+}
+
 fn human_friendly_mem(bytes: u64) -> String {
     let bytes_f = bytes as f64;
     let kb = bytes_f / 1024.0;
@@ -165,8 +373,8 @@ fn human_friendly_mem(bytes: u64) -> String {
 fn get_all_tracked(system: &mut System) -> Vec<Vec<String>> {
     system
         .processes()
-        .iter()
-        .map(|(_, proc)| {
+        .values()
+        .map(|proc| {
             proc.environ()
                 .iter()
                 .map(|s| s.to_string_lossy().into_owned())
@@ -196,19 +404,3 @@ fn get_all_session_processes(system: &mut System, session_pid: Pid) -> HashSet<P
     }
     all_pids_in_group
 }
-
-#[allow(dead_code)]
-fn get_all_children(system: &System, pid: Pid) -> HashSet<Pid> {
-    let mut children = HashSet::new();
-
-    for (proc_pid, process) in system.processes() {
-        if process.parent() == Some(pid) {
-            // Add the child PID
-            children.insert(*proc_pid);
-            // Recursively find its children
-            children.extend(get_all_children(system, *proc_pid));
-        }
-    }
-
-    children
-}
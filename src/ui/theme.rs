@@ -0,0 +1,196 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use ratatui::style::{Color, Modifier, Style};
+
+use crate::{ansi, config::Config};
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Colours of the TUI, set in the `[theme]` section of the config.
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    /// Borders of the header and the stats panel
+    pub border: Color,
+    /// Titles and labels
+    pub title: Color,
+    pub text: Color,
+    /// Descriptions, separators and cancelled runs
+    pub muted: Color,
+    /// Paths and action names
+    pub accent: Color,
+    /// Borders of overlays and icons without a colour of their own
+    pub info: Color,
+    /// Gauges and the CPU graph
+    pub gauge: Color,
+    /// The memory graph, command line flags and insert mode
+    pub secondary: Color,
+    pub success: Color,
+    /// Failed runs, tests and hooks, and errors in the output
+    pub failure: Color,
+    /// Running commands, warnings and pending changes
+    pub warning: Color,
+    /// Errors of amdo itself and nearly full gauges
+    pub error: Color,
+    /// Background of the selected item, reversed colours with `reset`
+    pub highlight: Color,
+    /// Whether file icons are shown in their own colours
+    icon_colors: bool,
+}
+
+const DARK: Theme = Theme {
+    border: Color::LightGreen,
+    title: Color::Yellow,
+    text: Color::White,
+    muted: Color::Gray,
+    accent: Color::LightCyan,
+    info: Color::LightBlue,
+    gauge: Color::Cyan,
+    secondary: Color::LightMagenta,
+    success: Color::Green,
+    failure: Color::LightRed,
+    warning: Color::LightYellow,
+    error: Color::Red,
+    highlight: Color::DarkGray,
+    icon_colors: true,
+};
+
+const LIGHT: Theme = Theme {
+    border: Color::Green,
+    title: Color::Blue,
+    text: Color::Black,
+    muted: Color::DarkGray,
+    accent: Color::Cyan,
+    info: Color::Blue,
+    gauge: Color::Blue,
+    secondary: Color::Magenta,
+    success: Color::Green,
+    failure: Color::Red,
+    // Yellow is unreadable on a white background
+    warning: Color::Indexed(130),
+    error: Color::Red,
+    highlight: Color::Gray,
+    icon_colors: true,
+};
+
+const HIGH_CONTRAST: Theme = Theme {
+    border: Color::White,
+    title: Color::LightYellow,
+    text: Color::White,
+    muted: Color::White,
+    accent: Color::LightCyan,
+    info: Color::White,
+    gauge: Color::LightCyan,
+    secondary: Color::LightMagenta,
+    success: Color::LightGreen,
+    failure: Color::LightRed,
+    warning: Color::LightYellow,
+    error: Color::LightRed,
+    highlight: Color::Reset,
+    icon_colors: false,
+};
+
+/// The terminal's own colours, for `NO_COLOR`.
+const NO_COLOR: Theme = Theme {
+    border: Color::Reset,
+    title: Color::Reset,
+    text: Color::Reset,
+    muted: Color::Reset,
+    accent: Color::Reset,
+    info: Color::Reset,
+    gauge: Color::Reset,
+    secondary: Color::Reset,
+    success: Color::Reset,
+    failure: Color::Reset,
+    warning: Color::Reset,
+    error: Color::Reset,
+    highlight: Color::Reset,
+    icon_colors: false,
+};
+
+impl Theme {
+    /// The theme named by `name` in the `[theme]` section, `dark` by default, with the colours
+    /// set next to it replaced, e.g. `title = magenta` or `border = #5f87af`. With `NO_COLOR` set,
+    /// the theme starts from the terminal's own colours instead.
+    pub fn load(config: &Config) -> Result<Theme, String> {
+        let mut theme = match (ansi::no_color(), config.get("theme", "name")) {
+            (true, _) => NO_COLOR,
+            (false, None | Some("dark")) => DARK,
+            (false, Some("light")) => LIGHT,
+            (false, Some("high-contrast")) => HIGH_CONTRAST,
+            (false, Some(name)) => {
+                return Err(format!(
+                    "Expected dark, light or high-contrast for name in [theme], got {:?}",
+                    name
+                ))
+            }
+        };
+        for (key, value) in config.section("theme") {
+            if key == "name" {
+                continue;
+            }
+            let color = Color::from_str(value).map_err(|_| {
+                format!(
+                    "Expected a colour name, index or #rrggbb for {} in [theme], got {:?}",
+                    key, value
+                )
+            })?;
+            *theme
+                .color_mut(key)
+                .ok_or_else(|| format!("Unknown colour {:?} in [theme]", key))? = color;
+        }
+        Ok(theme)
+    }
+
+    fn color_mut(&mut self, key: &str) -> Option<&mut Color> {
+        match key {
+            "border" => Some(&mut self.border),
+            "title" => Some(&mut self.title),
+            "text" => Some(&mut self.text),
+            "muted" => Some(&mut self.muted),
+            "accent" => Some(&mut self.accent),
+            "info" => Some(&mut self.info),
+            "gauge" => Some(&mut self.gauge),
+            "secondary" => Some(&mut self.secondary),
+            "success" => Some(&mut self.success),
+            "failure" => Some(&mut self.failure),
+            "warning" => Some(&mut self.warning),
+            "error" => Some(&mut self.error),
+            "highlight" => Some(&mut self.highlight),
+            _ => None,
+        }
+    }
+
+    /// Style of the selected item in lists.
+    pub fn highlight_style(&self) -> Style {
+        match self.highlight {
+            Color::Reset => Style::default().add_modifier(Modifier::REVERSED),
+            color => Style::default().bg(color),
+        }
+    }
+
+    /// Style of the filled part of a gauge, reversed when it has no colour so it still stands out
+    /// from the unfilled part.
+    pub fn gauge_style(&self, color: Color) -> Style {
+        match color {
+            Color::Reset => Style::default().add_modifier(Modifier::REVERSED),
+            color => Style::default().fg(color),
+        }
+    }
+
+    /// Colour of a file icon from its `#rrggbb` colour.
+    pub fn icon(&self, color: &str) -> Color {
+        match self.icon_colors {
+            true => Color::from_str(color).unwrap_or(self.info),
+            false => self.info,
+        }
+    }
+}
+
+/// Sets the theme of the TUI, before anything is drawn.
+pub fn init(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(|| DARK)
+}